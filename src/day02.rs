@@ -1,3 +1,19 @@
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Encoding {
+    Ascii,
+    Unicode,
+}
+
+impl Encoding {
+    pub fn detect(policy: &Policy, input: &str) -> Self {
+        if policy.letter.is_ascii() && input.is_ascii() {
+            Self::Ascii
+        } else {
+            Self::Unicode
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Debug)]
 pub struct Policy {
     letter: char,
@@ -6,14 +22,49 @@ pub struct Policy {
 }
 
 impl Policy {
-    fn validate_part1(&self, input: &str) -> bool {
-        let count = input.chars().filter(|&c| c == self.letter).count();
+    pub fn validate_part1(&self, input: &str) -> bool {
+        self.validate_part1_with(input, Encoding::detect(self, input))
+    }
+
+    pub fn validate_part2(&self, input: &str) -> bool {
+        self.validate_part2_with(input, Encoding::detect(self, input))
+    }
+
+    // `Encoding::Ascii` works on bytes wherever that gives the same answer as
+    // counting in chars, and falls back to `Encoding::Unicode` otherwise: always
+    // for a non-ASCII letter, and for part 2 also for a non-ASCII password.
+    pub fn validate_part1_with(&self, input: &str, encoding: Encoding) -> bool {
+        let count = match encoding {
+            Encoding::Ascii if self.letter.is_ascii() => {
+                bytecount::count(input.as_bytes(), self.letter as u8)
+            }
+            _ => input.chars().filter(|&c| c == self.letter).count(),
+        };
         self.left <= count && count <= self.right
     }
 
-    fn validate_part2(&self, input: &str) -> bool {
-        (input.chars().nth(self.left - 1) == Some(self.letter))
-            ^ (input.chars().nth(self.right - 1) == Some(self.letter))
+    pub fn validate_part2_with(&self, input: &str, encoding: Encoding) -> bool {
+        let (l, r) = match encoding {
+            Encoding::Ascii if self.letter.is_ascii() && input.is_ascii() => {
+                let bytes = input.as_bytes();
+                let letter = self.letter as u8;
+                (
+                    bytes.get(self.left - 1) == Some(&letter),
+                    bytes.get(self.right - 1) == Some(&letter),
+                )
+            }
+            _ => {
+                let mut chars = input.chars();
+                let l = chars.nth(self.left - 1);
+                let r = match self.right - self.left {
+                    0 => l,
+                    n => chars.nth(n - 1),
+                };
+                (l == Some(self.letter), r == Some(self.letter))
+            }
+        };
+
+        l ^ r
     }
 }
 
@@ -23,18 +74,22 @@ pub fn generator(input: &str) -> Option<Vec<(Policy, String)>> {
 }
 
 fn parse_line(line: &str) -> Option<(Policy, String)> {
-    let mut iter = line
-        .split([':', '-', ' '].as_ref())
-        .filter(|s| !s.is_empty());
-
-    Some((
-        Policy {
-            left: iter.next()?.parse().ok()?,
-            right: iter.next()?.parse().ok()?,
-            letter: iter.next()?.chars().next()?,
-        },
-        iter.next()?.to_owned(),
-    ))
+    let (policy, password) = line.split_once(':')?;
+    let (range, letter) = policy.trim().split_once(' ')?;
+    let (left, right) = range.split_once('-')?;
+
+    let mut letter = letter.trim().chars();
+    let policy = Policy {
+        left: left.parse().ok()?,
+        right: right.parse().ok()?,
+        letter: letter.next()?,
+    };
+
+    if letter.next().is_some() || policy.left == 0 || policy.left > policy.right {
+        return None;
+    }
+
+    Some((policy, password.trim().to_owned()))
 }
 
 #[aoc(day2, part1)]
@@ -47,6 +102,22 @@ pub fn part2(inputs: &[(Policy, String)]) -> usize {
     inputs.iter().filter(|(p, s)| p.validate_part2(s)).count()
 }
 
+#[aoc(day2, part1, bytes)]
+pub fn part1_bytes(inputs: &[(Policy, String)]) -> usize {
+    inputs
+        .iter()
+        .filter(|(p, s)| p.validate_part1_with(s, Encoding::Ascii))
+        .count()
+}
+
+#[aoc(day2, part2, bytes)]
+pub fn part2_bytes(inputs: &[(Policy, String)]) -> usize {
+    inputs
+        .iter()
+        .filter(|(p, s)| p.validate_part2_with(s, Encoding::Ascii))
+        .count()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -100,6 +171,58 @@ mod tests {
         assert_eq!(part2(&sample_input()), 1)
     }
 
+    #[test]
+    pub fn test_encodings_agree() {
+        let input = sample_input();
+        assert_eq!(part1_bytes(&input), part1(&input));
+        assert_eq!(part2_bytes(&input), part2(&input));
+
+        for (p, s) in input.iter() {
+            for encoding in [Encoding::Ascii, Encoding::Unicode] {
+                assert_eq!(p.validate_part1_with(s, encoding), p.validate_part1(s));
+                assert_eq!(p.validate_part2_with(s, encoding), p.validate_part2(s));
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_unicode() {
+        let input = generator("1-3 é: éabé\n2-3 ü: üüa\n1-2 a: äa").unwrap();
+        assert_eq!(
+            Encoding::detect(&input[0].0, &input[0].1),
+            Encoding::Unicode
+        );
+        assert_eq!(part1(&input), 3);
+        assert_eq!(part2(&input), 3);
+        // byte positions would drift once a multi-byte char precedes the letter
+        let (p, s) = &input[2];
+        assert!(p.validate_part2_with(s, Encoding::Unicode));
+        assert!(p.validate_part2_with(s, Encoding::Ascii));
+        assert_eq!(part1_bytes(&input), 3);
+        assert_eq!(part2_bytes(&input), 3);
+
+        // 'ł' is U+0142, which would truncate to the byte b'B'
+        let input = generator("1-3 ł: BBB\n1-2 ł: Bx\n1-2 ł: łB").unwrap();
+        assert_eq!(part1_bytes(&input), part1(&input));
+        assert_eq!(part2_bytes(&input), part2(&input));
+        for (p, s) in input.iter() {
+            for encoding in [Encoding::Ascii, Encoding::Unicode] {
+                assert_eq!(p.validate_part1_with(s, encoding), p.validate_part1(s));
+                assert_eq!(p.validate_part2_with(s, encoding), p.validate_part2(s));
+            }
+        }
+        assert_eq!(part1(&input), 1);
+        assert_eq!(part2(&input), 1);
+    }
+
+    #[test]
+    pub fn test_malformed() {
+        assert_eq!(parse_line("0-3 a: abc"), None);
+        assert_eq!(parse_line("3-1 a: abc"), None);
+        assert_eq!(parse_line("1-3 ab: abc"), None);
+        assert_eq!(parse_line("1-3 a abc"), None);
+    }
+
     mod regression {
         use super::*;
 
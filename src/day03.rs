@@ -1,3 +1,36 @@
+use std::ops::RangeInclusive;
+
+use num::rational::Ratio;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct Slope {
+    pub right: isize,
+    pub down: usize,
+}
+
+impl From<(isize, usize)> for Slope {
+    fn from((right, down): (isize, usize)) -> Self {
+        Self { right, down }
+    }
+}
+
+// A slope of `n/d` columns per row only lands on a square every `d` rows, so the
+// reduced ratio maps directly onto a (right, down) step.
+impl From<Ratio<isize>> for Slope {
+    fn from(ratio: Ratio<isize>) -> Self {
+        Self {
+            right: *ratio.numer(),
+            down: ratio.denom().unsigned_abs(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Goal {
+    Fewest,
+    Most,
+}
+
 pub struct Forest {
     field: Vec<Vec<u8>>,
     width: usize,
@@ -6,6 +39,10 @@ pub struct Forest {
 
 impl Forest {
     const TREE: u8 = b'#';
+    const HIT: u8 = b'X';
+    const MISS: u8 = b'O';
+    const OVERLAP: u8 = b'*';
+    const MAX_TILES: isize = 64;
 
     fn new(field: Vec<Vec<u8>>) -> Self {
         let width = field[0].len();
@@ -22,7 +59,14 @@ impl Forest {
         self.field[r][c] == Self::TREE
     }
 
-    fn count_trees(&self, c_inc: usize, r_inc: usize) -> usize {
+    // Returns `None` for a slope that never moves down, which would never reach
+    // the bottom of the forest.
+    pub fn count_trees(&self, c_inc: isize, r_inc: usize) -> Option<usize> {
+        if r_inc == 0 {
+            return None;
+        }
+
+        let c_inc = c_inc.rem_euclid(self.width as isize) as usize;
         let mut c = 0;
 
         (0..self.height)
@@ -38,6 +82,205 @@ impl Forest {
                 self.get(c_orig, r)
             })
             .count()
+            .into()
+    }
+
+    pub fn count_slope(&self, slope: impl Into<Slope>) -> Option<usize> {
+        let slope = slope.into();
+        self.count_trees(slope.right, slope.down)
+    }
+
+    // Returns the best tree count and every slope in range that achieves it.
+    pub fn search_slopes(
+        &self,
+        right: RangeInclusive<isize>,
+        down: RangeInclusive<usize>,
+        goal: Goal,
+    ) -> Option<(usize, Vec<Slope>)> {
+        let mut best: Option<(usize, Vec<Slope>)> = None;
+
+        for d in down {
+            for r in right.clone() {
+                let slope = Slope { right: r, down: d };
+                let Some(count) = self.count_slope(slope) else {
+                    continue;
+                };
+
+                match &mut best {
+                    Some((b, slopes)) if *b == count => slopes.push(slope),
+                    Some((b, _)) => {
+                        let better = match goal {
+                            Goal::Fewest => count < *b,
+                            Goal::Most => count > *b,
+                        };
+
+                        if better {
+                            best = Some((count, vec![slope]));
+                        }
+                    }
+                    None => best = Some((count, vec![slope])),
+                }
+            }
+        }
+
+        best
+    }
+
+    fn path(&self, slope: Slope) -> impl Iterator<Item = (usize, isize)> + use<> {
+        (0..self.height)
+            .step_by(slope.down)
+            .zip((0..).map(move |i| i * slope.right))
+            .skip(1)
+    }
+
+    pub fn render(&self, slope: impl Into<Slope>) -> Option<String> {
+        self.render_with(&[(slope.into(), Self::HIT, Self::MISS)])
+    }
+
+    // Each path is drawn with its own (hit, miss) symbols; squares visited by more
+    // than one path are drawn as `*`. The starting square is left unmarked, as in
+    // the puzzle statement. The pattern is repeated as far sideways as the paths go, up
+    // to `MAX_TILES` copies; wider paths are wrapped onto a single copy instead.
+    // Returns `None` if any slope never moves down, or moves further sideways than
+    // an `isize` can hold.
+    pub fn render_with(&self, paths: &[(Slope, u8, u8)]) -> Option<String> {
+        for &(slope, _, _) in paths {
+            if slope.down == 0 {
+                return None;
+            }
+
+            let steps = isize::try_from((self.height - 1) / slope.down).ok()?;
+            steps.checked_mul(slope.right)?;
+        }
+
+        let width = self.width as isize;
+        let (lo, hi) = paths
+            .iter()
+            .flat_map(|&(slope, _, _)| self.path(slope))
+            .fold((0, 0), |(lo, hi), (_, c)| (lo.min(c), hi.max(c)));
+
+        let (first, last) = (lo.div_euclid(width), hi.div_euclid(width));
+        let (left, tiles) = match last.checked_sub(first) {
+            Some(span) if span < Self::MAX_TILES => (first * width, span + 1),
+            _ => (0, 1),
+        };
+
+        let mut grid: Vec<Vec<u8>> = self
+            .field
+            .iter()
+            .map(|row| row.repeat(tiles as usize))
+            .collect();
+
+        for &(slope, hit, miss) in paths {
+            for (r, c) in self.path(slope) {
+                let cell = &mut grid[r][(c - left).rem_euclid(tiles * width) as usize];
+                *cell = match *cell {
+                    Self::TREE => hit,
+                    b'.' => miss,
+                    _ => Self::OVERLAP,
+                };
+            }
+        }
+
+        let rendered = grid
+            .iter()
+            .map(|row| String::from_utf8_lossy(row))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        Some(rendered)
+    }
+}
+
+// One bit per square, packed into 64-bit words stored row after row, so that many
+// slopes can be evaluated in a single pass over the rows.
+pub struct PackedForest {
+    words: Vec<u64>,
+    stride: usize,
+    width: usize,
+}
+
+impl From<&Forest> for PackedForest {
+    fn from(forest: &Forest) -> Self {
+        let words = forest
+            .field
+            .iter()
+            .flat_map(|row| {
+                row.chunks(64).map(|chunk| {
+                    chunk
+                        .iter()
+                        .enumerate()
+                        .filter(|&(_, &x)| x == Forest::TREE)
+                        .fold(0, |word, (i, _)| word | (1 << i))
+                })
+            })
+            .collect();
+
+        Self {
+            words,
+            stride: forest.width.div_ceil(64),
+            width: forest.width,
+        }
+    }
+}
+
+// The position and tally of one slope as the rows go by.
+struct Walker {
+    row: usize,
+    col: usize,
+    right: usize,
+    down: usize,
+    trees: usize,
+}
+
+impl Walker {
+    fn advance(&mut self, width: usize) {
+        self.row += self.down;
+        self.col += self.right;
+        if self.col >= width {
+            self.col -= width;
+        }
+    }
+}
+
+impl PackedForest {
+    // Returns the tree count for every slope, or `None` if any slope never moves
+    // down.
+    pub fn count_trees(&self, slopes: &[Slope]) -> Option<Vec<usize>> {
+        if slopes.iter().any(|s| s.down == 0) {
+            return None;
+        }
+
+        let mut walkers = slopes
+            .iter()
+            .map(|s| Walker {
+                row: 0,
+                col: 0,
+                right: s.right.rem_euclid(self.width as isize) as usize,
+                down: s.down,
+                trees: 0,
+            })
+            .collect::<Vec<_>>();
+
+        // Each row's words are fetched once and shared by every slope that lands on
+        // that row; rows of up to 64 squares are a single word.
+        if self.stride == 1 {
+            for (r, &word) in self.words.iter().enumerate() {
+                for w in walkers.iter_mut().filter(|w| w.row == r) {
+                    w.trees += ((word >> w.col) & 1) as usize;
+                    w.advance(self.width);
+                }
+            }
+        } else {
+            for (r, words) in self.words.chunks_exact(self.stride).enumerate() {
+                for w in walkers.iter_mut().filter(|w| w.row == r) {
+                    w.trees += ((words[w.col / 64] >> (w.col % 64)) & 1) as usize;
+                    w.advance(self.width);
+                }
+            }
+        }
+
+        Some(walkers.into_iter().map(|w| w.trees).collect())
     }
 }
 
@@ -47,18 +290,30 @@ pub fn generator(input: &str) -> Forest {
 }
 
 #[aoc(day3, part1)]
-pub fn part1(inputs: &Forest) -> usize {
+pub fn part1(inputs: &Forest) -> Option<usize> {
     inputs.count_trees(3, 1)
 }
 
 #[aoc(day3, part2)]
-pub fn part2(inputs: &Forest) -> usize {
+pub fn part2(inputs: &Forest) -> Option<usize> {
     [(1, 1), (3, 1), (5, 1), (7, 1), (1, 2)]
         .iter()
         .map(|&(c, r)| inputs.count_trees(c, r))
         .product()
 }
 
+#[aoc_generator(day3, part2, packed)]
+pub fn generator_packed(input: &str) -> PackedForest {
+    PackedForest::from(&generator(input))
+}
+
+#[aoc(day3, part2, packed)]
+pub fn part2_packed(inputs: &PackedForest) -> Option<usize> {
+    inputs
+        .count_trees(&[(1, 1), (3, 1), (5, 1), (7, 1), (1, 2)].map(Slope::from))
+        .map(|counts| counts.into_iter().product())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -92,12 +347,234 @@ mod tests {
 
     #[test]
     pub fn test1() {
-        assert_eq!(part1(&generator(SAMPLE)), 7);
+        assert_eq!(part1(&generator(SAMPLE)), Some(7));
     }
 
     #[test]
     pub fn test2() {
-        assert_eq!(part2(&generator(SAMPLE)), 336);
+        assert_eq!(part2(&generator(SAMPLE)), Some(336));
+    }
+
+    #[test]
+    pub fn test_wrapping() {
+        let forest = generator(SAMPLE);
+        let width = forest.width as isize;
+
+        for c in 0..width {
+            let expected = forest.count_trees(c, 1);
+            assert_eq!(forest.count_trees(c + width, 1), expected);
+            assert_eq!(forest.count_trees(c + 3 * width, 1), expected);
+            assert_eq!(forest.count_trees(c - width, 1), expected);
+        }
+        assert_eq!(forest.count_trees(-8, 1), Some(7));
+    }
+
+    #[test]
+    pub fn test_ratio() {
+        let forest = generator(SAMPLE);
+
+        assert_eq!(Slope::from(Ratio::new(2, 4)), Slope { right: 1, down: 2 });
+        assert_eq!(Slope::from(Ratio::new(1, -3)), Slope { right: -1, down: 3 });
+        assert_eq!(forest.count_slope(Ratio::new(1, 2)), Some(2));
+        assert_eq!(forest.count_slope(Ratio::from_integer(3)), Some(7));
+        assert_eq!(forest.count_slope((7, 1)), Some(4));
+    }
+
+    #[test]
+    pub fn test_render_wide() {
+        let forest = generator(SAMPLE);
+
+        assert_eq!(forest.render((isize::MAX, 1)), None);
+        assert_eq!(forest.render((isize::MIN / 5, 1)), None);
+        assert_eq!(forest.render((isize::MAX, 11)).map(|r| r.len()), Some(131));
+
+        // Too wide to repeat, so the path is wrapped onto a single copy, landing
+        // where the narrow slope with the same step modulo the width does.
+        let wide = forest.render((3 + 11 * 1000, 1)).unwrap();
+        assert!(wide.lines().all(|line| line.len() == 11));
+        for (r, line) in wide.lines().enumerate().skip(1) {
+            assert_eq!(line.find(['X', 'O']), Some(3 * r % 11), "{line}");
+        }
+        assert_eq!(
+            wide.bytes().filter(|&b| b == b'X').count(),
+            part1(&forest).unwrap()
+        );
+    }
+
+    #[test]
+    pub fn test_zero_down() {
+        let forest = generator(SAMPLE);
+
+        assert_eq!(forest.count_trees(3, 0), None);
+        assert_eq!(forest.count_slope((0, 0)), None);
+        assert_eq!(forest.render((1, 0)), None);
+        assert_eq!(
+            forest.render_with(&[
+                (Slope { right: 1, down: 1 }, b'X', b'O'),
+                (Slope { right: 1, down: 0 }, b'X', b'O'),
+            ]),
+            None
+        );
+        assert_eq!(
+            forest.search_slopes(3..=3, 0..=1, Goal::Most),
+            Some((7, vec![Slope { right: 3, down: 1 }]))
+        );
+    }
+
+    #[test]
+    pub fn test_search() {
+        let forest = generator(SAMPLE);
+
+        assert_eq!(
+            forest.search_slopes(1..=7, 1..=1, Goal::Most),
+            Some((7, vec![Slope { right: 3, down: 1 }]))
+        );
+        assert_eq!(forest.search_slopes(1..=7, 0..=0, Goal::Most), None);
+        assert_eq!(
+            forest.search_slopes(-6..=6, 1..=2, Goal::Fewest),
+            Some((
+                0,
+                vec![Slope { right: -6, down: 2 }, Slope { right: 5, down: 2 }]
+            ))
+        );
+    }
+
+    #[test]
+    pub fn test_render() {
+        let forest = generator(SAMPLE);
+
+        assert_eq!(
+            forest.render((3, 1)).unwrap(),
+            r"..##.........##.........##.......
+#..O#...#..#...#...#..#...#...#..
+.#....X..#..#....#..#..#....#..#.
+..#.#...#O#..#.#...#.#..#.#...#.#
+.#...##..#..X...##..#..#...##..#.
+..#.##.......#.X#.......#.##.....
+.#.#.#....#.#.#.#.O..#.#.#.#....#
+.#........#.#........X.#........#
+#.##...#...#.##...#...#.X#...#...
+#...##....##...##....##...#X....#
+.#..#...#.#.#..#...#.#.#..#...X.#"
+        );
+        assert_eq!(
+            forest
+                .render((3, 1))
+                .unwrap()
+                .bytes()
+                .filter(|&b| b == b'X')
+                .count(),
+            part1(&forest).unwrap()
+        );
+    }
+
+    #[test]
+    pub fn test_render_with() {
+        let forest = generator(SAMPLE);
+
+        assert_eq!(
+            forest.render_with(&[
+                (Slope { right: 1, down: 2 }, b'A', b'a'),
+                (Slope { right: -1, down: 1 }, b'B', b'b')
+            ]),
+            Some(
+                r"..##.........##.......
+#...#...#.b#...#...#..
+.#....#..B..A....#..#.
+..#.#...B.#..#.#...#.#
+.#...##b.#..#a..##..#.
+..#.##b......#.##.....
+.#.#.B....#.#.A.#....#
+.#..b.....#.#........#
+#.#B...#...#.##a..#...
+#.b.##....##...##....#
+.B..#...#.#.#..#a..#.#"
+                    .to_owned()
+            )
+        );
+
+        let overlapping = forest.render_with(&[
+            (Slope { right: 1, down: 1 }, b'X', b'O'),
+            (Slope { right: 2, down: 2 }, b'X', b'O'),
+        ]);
+        assert_eq!(
+            overlapping.unwrap().bytes().filter(|&b| b == b'*').count(),
+            5
+        );
+    }
+
+    // A forest of the given size with roughly one tree in four squares, from a
+    // simple LCG.
+    fn random_forest(height: usize, width: usize) -> Forest {
+        let mut seed = 2020_u64;
+        let field = (0..height)
+            .map(|_| {
+                (0..width)
+                    .map(|_| {
+                        seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1);
+                        if seed >> 62 == 0 { b'#' } else { b'.' }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Forest::new(field)
+    }
+
+    #[test]
+    pub fn test_packed() {
+        assert_eq!(part2_packed(&generator_packed(SAMPLE)), Some(336));
+
+        // A tall, wide (multi-word) forest.
+        let forest = random_forest(10_000, 150);
+        let packed = PackedForest::from(&forest);
+
+        let slopes = (-160..=160)
+            .step_by(7)
+            .flat_map(|right| (1..=4).map(move |down| Slope { right, down }))
+            .collect::<Vec<_>>();
+        let expected = slopes
+            .iter()
+            .map(|&s| forest.count_slope(s).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(packed.count_trees(&slopes), Some(expected));
+        assert_eq!(
+            packed.count_trees(&[Slope { right: 1, down: 1 }, Slope { right: 1, down: 0 }]),
+            None
+        );
+    }
+
+    // Compares the byte grid against the packed forest on a tall forest of the
+    // puzzle's width. Run with
+    // `cargo test --release day03::tests::bench_packed -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    pub fn bench_packed() {
+        const RUNS: u32 = 20;
+
+        let forest = random_forest(200_000, 31);
+        let packed = PackedForest::from(&forest);
+        let slopes = [(1, 1), (3, 1), (5, 1), (7, 1), (1, 2)].map(Slope::from);
+
+        let start = std::time::Instant::now();
+        let mut grid_counts = Vec::new();
+        for _ in 0..RUNS {
+            grid_counts = slopes
+                .iter()
+                .map(|&s| forest.count_slope(s).unwrap())
+                .collect();
+        }
+        let grid_time = start.elapsed() / RUNS;
+
+        let start = std::time::Instant::now();
+        let mut packed_counts = Vec::new();
+        for _ in 0..RUNS {
+            packed_counts = packed.count_trees(&slopes).unwrap();
+        }
+        let packed_time = start.elapsed() / RUNS;
+
+        println!("byte grid: {grid_time:?}, packed: {packed_time:?}");
+        assert_eq!(packed_counts, grid_counts);
     }
 
     mod regression {
@@ -109,8 +586,9 @@ mod tests {
         #[test]
         pub fn test() {
             let input = INPUT.trim_end_matches('\n'); // Trims trailing newline
-            assert_eq!(part1(&generator(input)), ANSWERS.0);
-            assert_eq!(part2(&generator(input)), ANSWERS.1);
+            assert_eq!(part1(&generator(input)), Some(ANSWERS.0));
+            assert_eq!(part2(&generator(input)), Some(ANSWERS.1));
+            assert_eq!(part2_packed(&generator_packed(input)), Some(ANSWERS.1));
         }
     }
 }
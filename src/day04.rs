@@ -1,35 +1,197 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, error::Error, fmt, fs, path::Path, str::FromStr};
+
+// Schema lines are `<key> <required|optional> <rule> [arguments...]`, where rule is
+// one of `any`, `range LO HI`, `units SUFFIX LO HI [SUFFIX LO HI]...`,
+// `enum VALUE...` or `pattern PATTERN`. Lines starting with `#` are comments.
+pub const DEFAULT_SCHEMA: &str = r"# key presence rule arguments
+byr required range 1920 2002
+iyr required range 2010 2020
+eyr required range 2020 2030
+hgt required units cm 150 193 in 59 76
+hcl required pattern #[0-9a-fA-F]{6}
+ecl required enum amb blu brn gry grn hzl oth
+pid required pattern [0-9]{9}
+cid optional any";
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct Passport(BTreeMap<String, String>);
 
-impl Passport {
-    fn is_valid(&self) -> bool {
-        self.0
-            .keys()
-            .filter(|&key| key != "cid")
-            .eq(["byr", "ecl", "eyr", "hcl", "hgt", "iyr", "pid"].iter())
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "schema line {}: {}", self.line, self.message)
     }
+}
 
-    fn is_valid_values(&self) -> bool {
-        self.is_valid()
-            && self
-                .0
-                .iter()
-                .all(|(key, value)| Self::validate_value(key, value))
+impl Error for SchemaError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum CharClass {
+    Any,
+    Literal(char),
+    Set(Vec<(char, char)>),
+}
+
+impl CharClass {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            CharClass::Any => true,
+            CharClass::Literal(l) => *l == c,
+            CharClass::Set(ranges) => ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi),
+        }
+    }
+}
+
+// A small anchored regex subset: literals, `\` escapes, `.`, `[...]` classes with
+// ranges, and the quantifiers `?`, `*`, `+`, `{n}` and `{n,m}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    source: String,
+    atoms: Vec<(CharClass, usize, usize)>,
+}
+
+impl FromStr for Pattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut atoms = Vec::new();
+        let mut chars = s.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            let class = match c {
+                '.' => CharClass::Any,
+                '\\' => CharClass::Literal(chars.next().ok_or("dangling escape")?),
+                '[' => {
+                    let mut ranges = Vec::new();
+                    loop {
+                        let lo = match chars.next().ok_or("unclosed character class")? {
+                            ']' => break,
+                            '\\' => chars.next().ok_or("dangling escape")?,
+                            c => c,
+                        };
+                        if chars.peek() == Some(&'-') {
+                            chars.next();
+                            let hi = chars.next().ok_or("unclosed character class")?;
+                            ranges.push((lo, hi));
+                        } else {
+                            ranges.push((lo, lo));
+                        }
+                    }
+                    CharClass::Set(ranges)
+                }
+                '?' | '*' | '+' | '{' | ']' => return Err(format!("unexpected '{c}'")),
+                c => CharClass::Literal(c),
+            };
+
+            let (min, max) = match chars.next_if(|&c| matches!(c, '?' | '*' | '+' | '{')) {
+                Some('?') => (0, 1),
+                Some('*') => (0, usize::MAX),
+                Some('+') => (1, usize::MAX),
+                Some(_) => {
+                    let mut body = String::new();
+                    loop {
+                        match chars.next().ok_or("unclosed repeat")? {
+                            '}' => break,
+                            c => body.push(c),
+                        }
+                    }
+                    let bound = |n: &str| {
+                        n.trim()
+                            .parse()
+                            .map_err(|_| format!("bad repeat {{{body}}}"))
+                    };
+                    match body.split_once(',') {
+                        Some((min, max)) => (bound(min)?, bound(max)?),
+                        None => (bound(&body)?, bound(&body)?),
+                    }
+                }
+                None => (1, 1),
+            };
+            if let Some(c) = chars.next_if(|&c| matches!(c, '?' | '*' | '+' | '{')) {
+                return Err(format!("stacked quantifier '{c}'"));
+            }
+            if min > max {
+                return Err(format!("bad repeat {{{min},{max}}}"));
+            }
+
+            atoms.push((class, min, max));
+        }
+
+        Ok(Self {
+            source: s.to_owned(),
+            atoms,
+        })
     }
+}
+
+impl Pattern {
+    // Tracks every position the atoms so far can end at, rather than backtracking,
+    // so matching takes time linear in the pattern and value lengths.
+    pub fn is_match(&self, s: &str) -> bool {
+        let s = s.chars().collect::<Vec<_>>();
+        let mut reachable = vec![false; s.len() + 1];
+        reachable[0] = true;
+
+        for (class, min, max) in self.atoms.iter() {
+            // `run[i]` counts the characters from `i` on that `class` matches.
+            let mut run = vec![0; s.len() + 1];
+            for i in (0..s.len()).rev() {
+                if class.matches(s[i]) {
+                    run[i] = run[i + 1] + 1;
+                }
+            }
 
-    fn validate_value(key: &str, value: &str) -> bool {
-        match key {
-            "byr" => Self::is_valid_range(value, 1920, 2002),
-            "iyr" => Self::is_valid_range(value, 2010, 2020),
-            "eyr" => Self::is_valid_range(value, 2020, 2030),
-            "hgt" => Self::is_valid_height(value),
-            "hcl" => Self::is_valid_haircolor(value),
-            "ecl" => ["amb", "blu", "brn", "gry", "grn", "hzl", "oth"].contains(&value),
-            "pid" => value.len() == 9 && value.bytes().all(|x| x.is_ascii_digit()),
-            "cid" => true,
-            _ => false,
+            // Each reachable start extends to a range of ends, marked with a
+            // difference array.
+            let mut delta = vec![0_isize; s.len() + 2];
+            for i in (0..=s.len()).filter(|&i| reachable[i] && run[i] >= *min) {
+                delta[i + min] += 1;
+                delta[i + run[i].min(*max) + 1] -= 1;
+            }
+
+            let mut open = 0;
+            for (i, r) in reachable.iter_mut().enumerate() {
+                open += delta[i];
+                *r = open > 0;
+            }
+        }
+
+        reachable[s.len()]
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rule {
+    Any,
+    Range(usize, usize),
+    Units(Vec<(String, usize, usize)>),
+    Enumeration(Vec<String>),
+    Pattern(Pattern),
+}
+
+impl Rule {
+    pub fn check(&self, value: &str) -> bool {
+        match self {
+            Rule::Any => true,
+            Rule::Range(lower, upper) => Self::is_valid_range(value, *lower, *upper),
+            Rule::Units(units) => units.iter().any(|(suffix, lower, upper)| {
+                value
+                    .strip_suffix(suffix.as_str())
+                    .is_some_and(|n| Self::is_valid_range(n, *lower, *upper))
+            }),
+            Rule::Enumeration(values) => values.iter().any(|v| v == value),
+            Rule::Pattern(pattern) => pattern.is_match(value),
         }
     }
 
@@ -39,52 +201,396 @@ impl Passport {
             .unwrap_or(false)
     }
 
-    fn is_valid_height(height: &str) -> bool {
-        if let Some(centimeter) = height.strip_suffix("cm") {
-            Self::is_valid_range(centimeter, 150, 193)
-        } else if let Some(inches) = height.strip_suffix("in") {
-            Self::is_valid_range(inches, 59, 76)
-        } else {
-            false
+    fn parse(name: &str, args: &[&str]) -> Result<Self, String> {
+        let number = |s: &str| s.parse().map_err(|_| format!("invalid number '{s}'"));
+
+        match (name, args) {
+            ("any", []) => Ok(Rule::Any),
+            ("range", [lower, upper]) => Ok(Rule::Range(number(lower)?, number(upper)?)),
+            ("units", args) if !args.is_empty() && args.len() % 3 == 0 => args
+                .chunks(3)
+                .map(|c| Ok((c[0].to_owned(), number(c[1])?, number(c[2])?)))
+                .collect::<Result<_, String>>()
+                .map(Rule::Units),
+            ("enum", args) if !args.is_empty() => Ok(Rule::Enumeration(
+                args.iter().map(|s| (*s).to_owned()).collect(),
+            )),
+            ("pattern", [pattern]) => pattern.parse().map(Rule::Pattern),
+            ("any" | "range" | "units" | "enum" | "pattern", _) => {
+                Err(format!("wrong arguments for rule '{name}'"))
+            }
+            _ => Err(format!("unknown rule '{name}'")),
         }
     }
+}
 
-    fn is_valid_haircolor(color: &str) -> bool {
-        color.len() == 7 && {
-            let mut iter = color.bytes();
-            iter.next() == Some(b'#') && iter.all(|x| x.is_ascii_hexdigit())
+// Rules display in the same syntax the schema file uses.
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rule::Any => write!(f, "any"),
+            Rule::Range(lower, upper) => write!(f, "range {lower} {upper}"),
+            Rule::Units(units) => {
+                write!(f, "units")?;
+                for (suffix, lower, upper) in units {
+                    write!(f, " {suffix} {lower} {upper}")?;
+                }
+                Ok(())
+            }
+            Rule::Enumeration(values) => write!(f, "enum {}", values.join(" ")),
+            Rule::Pattern(pattern) => write!(f, "pattern {pattern}"),
         }
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidValue {
+    pub key: String,
+    pub value: String,
+    pub rule: Rule,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub missing: Vec<String>,
+    pub unknown: Vec<String>,
+    pub invalid: Vec<InvalidValue>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if *self == Report::default() {
+            return write!(f, "valid");
+        }
+
+        let mut problems = Vec::new();
+        if !self.missing.is_empty() {
+            problems.push(format!("missing {}", self.missing.join(", ")));
+        }
+        if !self.unknown.is_empty() {
+            problems.push(format!("unknown {}", self.unknown.join(", ")));
+        }
+        problems.extend(
+            self.invalid
+                .iter()
+                .map(|i| format!("{}:{} breaks `{}`", i.key, i.value, i.rule)),
+        );
+
+        write!(f, "{}", problems.join("; "))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub key: String,
+    pub required: bool,
+    pub rule: Rule,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schema {
+    fields: Vec<Field>,
+}
+
+impl Default for Schema {
+    fn default() -> Self {
+        DEFAULT_SCHEMA.parse().unwrap()
+    }
+}
+
+impl FromStr for Schema {
+    type Err = SchemaError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields: Vec<Field> = Vec::new();
+
+        for (line, text) in s.lines().enumerate().map(|(i, l)| (i + 1, l.trim())) {
+            if text.is_empty() || text.starts_with('#') {
+                continue;
+            }
+
+            let error = |message: String| SchemaError { line, message };
+            let words = text.split_whitespace().collect::<Vec<_>>();
+            let [key, presence, rule, args @ ..] = words.as_slice() else {
+                return Err(error("expected '<key> <presence> <rule>'".to_owned()));
+            };
+
+            let required = match *presence {
+                "required" => true,
+                "optional" => false,
+                _ => return Err(error(format!("unknown presence '{presence}'"))),
+            };
+            if fields.iter().any(|f| f.key == *key) {
+                return Err(error(format!("duplicate key '{key}'")));
+            }
+
+            fields.push(Field {
+                key: (*key).to_owned(),
+                required,
+                rule: Rule::parse(rule, args).map_err(error)?,
+            });
+        }
+
+        Ok(Self { fields })
+    }
+}
+
+impl Schema {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        Ok(fs::read_to_string(path)?.parse()?)
+    }
+
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+
+    pub fn field(&self, key: &str) -> Option<&Field> {
+        self.fields.iter().find(|f| f.key == key)
+    }
+
+    // All required keys are present and every key is known to the schema.
+    pub fn has_fields(&self, passport: &Passport) -> bool {
+        self.fields
+            .iter()
+            .filter(|f| f.required)
+            .all(|f| passport.0.contains_key(&f.key))
+            && passport.0.keys().all(|key| self.field(key).is_some())
+    }
+
+    pub fn validate_value(&self, key: &str, value: &str) -> bool {
+        self.field(key).is_some_and(|f| f.rule.check(value))
+    }
+
+    pub fn is_valid(&self, passport: &Passport) -> bool {
+        self.has_fields(passport)
+            && passport
+                .0
+                .iter()
+                .all(|(key, value)| self.validate_value(key, value))
+    }
+
+    pub fn validate(&self, passport: &Passport) -> Report {
+        let mut report = Report {
+            missing: self
+                .fields
+                .iter()
+                .filter(|f| f.required && !passport.0.contains_key(&f.key))
+                .map(|f| f.key.clone())
+                .collect(),
+            ..Report::default()
+        };
+
+        for (key, value) in passport.0.iter() {
+            match self.field(key) {
+                None => report.unknown.push(key.clone()),
+                Some(field) if !field.rule.check(value) => report.invalid.push(InvalidValue {
+                    key: key.clone(),
+                    value: value.clone(),
+                    rule: field.rule.clone(),
+                }),
+                Some(_) => {}
+            }
+        }
+
+        report
+    }
+
+    // Schema fields in schema order, followed by any unknown fields in key order.
+    pub fn canonical_fields<'a>(&self, passport: &'a Passport) -> Vec<(&'a str, &'a str)> {
+        let known = self
+            .fields
+            .iter()
+            .filter_map(|f| passport.0.get_key_value(&f.key));
+        let unknown = passport
+            .0
+            .iter()
+            .filter(|(key, _)| self.field(key).is_none());
+
+        known
+            .chain(unknown)
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect()
+    }
+
+    pub fn normalize(&self, passport: &Passport) -> String {
+        join_fields(self.canonical_fields(passport))
+    }
+
+    pub fn to_batch(&self, passports: &[Passport]) -> String {
+        passports
+            .iter()
+            .map(|p| self.normalize(p))
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+fn join_fields<'a>(fields: impl IntoIterator<Item = (&'a str, &'a str)>) -> String {
+    fields
+        .into_iter()
+        .map(|(key, value)| format!("{key}:{value}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl fmt::Display for Passport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&join_fields(
+            self.0.iter().map(|(k, v)| (k.as_str(), v.as_str())),
+        ))
+    }
+}
+
+pub fn to_batch(passports: &[Passport]) -> String {
+    passports
+        .iter()
+        .map(|p| p.to_string())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IssueKind {
+    Duplicate(String),
+    Malformed(String),
+}
+
+// Positions are 1-based; the column counts chars.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseIssue {
+    pub line: usize,
+    pub column: usize,
+    pub kind: IssueKind,
+}
+
+impl fmt::Display for ParseIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}: ", self.line, self.column)?;
+        match &self.kind {
+            IssueKind::Duplicate(key) => write!(f, "duplicate key '{key}'"),
+            IssueKind::Malformed(field) => write!(f, "malformed field '{field}'"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordIssues {
+    pub record: usize,
+    pub line: usize,
+    pub issues: Vec<ParseIssue>,
+}
+
+// Every record with an issue is described in `issues`. A duplicate key keeps its
+// first value and the record is still kept; records with a malformed field are
+// left out of `passports`.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Batch {
+    pub passports: Vec<Passport>,
+    pub issues: Vec<RecordIssues>,
+}
+
+fn tokens(line: &str) -> Vec<(usize, &str)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+
+    for (i, c) in line
+        .char_indices()
+        .chain(std::iter::once((line.len(), ' ')))
+    {
+        match (start, c.is_whitespace()) {
+            (None, false) => start = Some(i),
+            (Some(s), true) => {
+                tokens.push((line[..s].chars().count() + 1, &line[s..i]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+
+    tokens
+}
+
+pub fn parse_batch(input: &str) -> Batch {
+    let mut batch = Batch::default();
+    let mut record = 0;
+    let mut lines = input
+        .lines()
+        .enumerate()
+        .map(|(i, l)| (i + 1, l))
+        .peekable();
+
+    while lines.peek().is_some() {
+        let mut fields = BTreeMap::new();
+        let mut issues = Vec::new();
+        let mut first_line = None;
+
+        for (line, text) in lines.by_ref() {
+            let tokens = tokens(text);
+            if tokens.is_empty() {
+                if first_line.is_some() {
+                    break;
+                }
+                continue;
+            }
+            first_line.get_or_insert(line);
+
+            for (column, field) in tokens {
+                let issue = |kind| ParseIssue { line, column, kind };
+
+                match field.split_once(':') {
+                    Some((key, value)) if !key.is_empty() && !value.is_empty() => {
+                        if fields.contains_key(key) {
+                            issues.push(issue(IssueKind::Duplicate(key.to_owned())));
+                        } else {
+                            fields.insert(key.to_owned(), value.to_owned());
+                        }
+                    }
+                    _ => issues.push(issue(IssueKind::Malformed(field.to_owned()))),
+                }
+            }
+        }
+
+        let Some(line) = first_line else {
+            break;
+        };
+
+        if !issues
+            .iter()
+            .any(|i| matches!(i.kind, IssueKind::Malformed(_)))
+        {
+            batch.passports.push(Passport(fields));
+        }
+        if !issues.is_empty() {
+            batch.issues.push(RecordIssues {
+                record,
+                line,
+                issues,
+            });
+        }
+        record += 1;
+    }
+
+    batch
+}
+
 #[aoc_generator(day4)]
-pub fn generator(input: &str) -> Option<Vec<Passport>> {
-    input
-        .split("\n\n")
-        .map(|section| {
-            section
-                .split(&[' ', '\n'][..])
-                .map(|field| {
-                    let mut iter = field.split(':');
-
-                    iter.next()
-                        .map(|s| s.to_owned())
-                        .and_then(|first| iter.next().map(|s| (first, s.to_owned())))
-                })
-                .collect::<Option<_>>()
-                .map(Passport)
-        })
-        .collect()
+pub fn generator(input: &str) -> Vec<Passport> {
+    parse_batch(input).passports
 }
 
 #[aoc(day4, part1)]
 pub fn part1(inputs: &[Passport]) -> usize {
-    inputs.iter().filter(|&pass| pass.is_valid()).count()
+    let schema = Schema::default();
+    inputs
+        .iter()
+        .filter(|&pass| schema.has_fields(pass))
+        .count()
 }
 
 #[aoc(day4, part2)]
 pub fn part2(inputs: &[Passport]) -> usize {
-    inputs.iter().filter(|&pass| pass.is_valid_values()).count()
+    let schema = Schema::default();
+    inputs.iter().filter(|&pass| schema.is_valid(pass)).count()
 }
 
 #[cfg(test)]
@@ -136,7 +642,6 @@ pid:3556412378 byr:2007";
     pub fn test_input() {
         assert_eq!(
             generator(SAMPLE)
-                .unwrap()
                 .iter()
                 .map(|passport| passport
                     .0
@@ -187,17 +692,285 @@ pid:3556412378 byr:2007";
 
     #[test]
     pub fn test1() {
-        assert_eq!(part1(&generator(SAMPLE).unwrap()), 2);
+        assert_eq!(part1(&generator(SAMPLE)), 2);
     }
 
     #[test]
     pub fn test2_valid() {
-        assert_eq!(part2(&generator(VALID).unwrap()), 4);
+        assert_eq!(part2(&generator(VALID)), 4);
     }
 
     #[test]
     pub fn test2_invalid() {
-        assert_eq!(part2(&generator(INVALID).unwrap()), 0);
+        assert_eq!(part2(&generator(INVALID)), 0);
+    }
+
+    #[test]
+    pub fn test_default_schema() {
+        let schema = Schema::default();
+        assert_eq!(schema.fields().len(), 8);
+
+        for (key, value, expected) in [
+            ("byr", "2002", true),
+            ("byr", "2003", false),
+            ("hgt", "60in", true),
+            ("hgt", "190cm", true),
+            ("hgt", "190in", false),
+            ("hgt", "190", false),
+            ("hcl", "#123abc", true),
+            ("hcl", "#123abz", false),
+            ("hcl", "123abc", false),
+            ("ecl", "brn", true),
+            ("ecl", "wat", false),
+            ("pid", "000000001", true),
+            ("pid", "0123456789", false),
+            ("cid", "anything", true),
+            ("xyz", "1", false),
+        ] {
+            assert_eq!(schema.validate_value(key, value), expected, "{key}:{value}");
+        }
+    }
+
+    #[test]
+    pub fn test_pattern() {
+        let pattern = |p: &str| p.parse::<Pattern>().unwrap();
+
+        assert!(pattern("a.c").is_match("abc"));
+        assert!(!pattern("a.c").is_match("abcd"));
+        assert!(pattern("ab?c").is_match("ac"));
+        assert!(pattern("a[0-9]*b").is_match("a0123b"));
+        assert!(pattern("a[0-9]+").is_match("a9"));
+        assert!(!pattern("a[0-9]+").is_match("a"));
+        assert!(pattern("[a-c]{2,3}c").is_match("abc"));
+        assert!(!pattern("[a-c]{2,3}c").is_match("ac"));
+        assert!(pattern("\\.x").is_match(".x"));
+        assert!(!pattern("\\.x").is_match("ax"));
+
+        assert!("[abc".parse::<Pattern>().is_err());
+        assert!("a{3,1}".parse::<Pattern>().is_err());
+        assert!("*a".parse::<Pattern>().is_err());
+        assert_eq!(
+            "[0-9]{9".parse::<Pattern>(),
+            Err("unclosed repeat".to_owned())
+        );
+        assert!("a{".parse::<Pattern>().is_err());
+        assert_eq!(
+            "a{2}*".parse::<Pattern>(),
+            Err("stacked quantifier '*'".to_owned())
+        );
+        assert!("a*?".parse::<Pattern>().is_err());
+        assert!("a+{2}".parse::<Pattern>().is_err());
+        assert!("a{1,2}{3}".parse::<Pattern>().is_err());
+
+        // Nested optional repeats used to backtrack exponentially.
+        let slow = pattern(&("a?".repeat(30) + "a{30}"));
+        assert!(slow.is_match(&"a".repeat(30)));
+        assert!(!slow.is_match(&"a".repeat(29)));
+        let slow = pattern(&("a*".repeat(20) + "b"));
+        assert!(!slow.is_match(&"a".repeat(30)));
+        assert!(pattern("x{0}y").is_match("y"));
+        assert!(!pattern("a{40}").is_match(&"a".repeat(30)));
+    }
+
+    #[test]
+    pub fn test_custom_schema() {
+        let schema: Schema = r"# library cards
+id required pattern L[0-9]{4}
+kind required enum adult child
+age optional range 0 120"
+            .parse()
+            .unwrap();
+        let records = generator(
+            "id:L0042 kind:child age:9\n\nid:L12 kind:adult\n\nid:L0001 kind:adult zip:1",
+        );
+
+        assert_eq!(
+            records
+                .iter()
+                .map(|r| schema.is_valid(r))
+                .collect::<Vec<_>>(),
+            vec![true, false, false]
+        );
+    }
+
+    #[test]
+    pub fn test_schema_errors() {
+        let error = |s: &str| s.parse::<Schema>().unwrap_err();
+
+        assert_eq!(error("byr required").line, 1);
+        assert_eq!(error("# c\n\nbyr sometimes any").line, 3);
+        assert_eq!(
+            error("byr required range 1 x").message,
+            "invalid number 'x'"
+        );
+        assert_eq!(
+            error("byr required regex x").message,
+            "unknown rule 'regex'"
+        );
+        assert_eq!(
+            error("byr required any\nbyr optional any").message,
+            "duplicate key 'byr'"
+        );
+        assert_eq!(
+            error("hgt required units cm 1").message,
+            "wrong arguments for rule 'units'"
+        );
+    }
+
+    #[test]
+    pub fn test_report() {
+        let schema = Schema::default();
+        let passports = generator(INVALID);
+        let reports = passports
+            .iter()
+            .map(|p| schema.validate(p))
+            .collect::<Vec<_>>();
+
+        assert!(passports.iter().all(|p| !schema.is_valid(p)));
+        assert!(reports.iter().all(|r| *r != Report::default()));
+        assert_eq!(
+            reports[0].to_string(),
+            "eyr:1972 breaks `range 2020 2030`; hgt:170 breaks `units cm 150 193 in 59 76`; \
+             pid:186cm breaks `pattern [0-9]{9}`"
+        );
+        assert_eq!(
+            reports[2].invalid,
+            vec![InvalidValue {
+                key: "hcl".to_owned(),
+                value: "dab227".to_owned(),
+                rule: Rule::Pattern("#[0-9a-fA-F]{6}".parse().unwrap()),
+            }]
+        );
+
+        let passports = generator(SAMPLE);
+        let report = schema.validate(&passports[1]);
+        assert_eq!(report.missing, vec!["hgt"]);
+        assert!(!schema.has_fields(&passports[1]));
+        assert_eq!(report.to_string(), "missing hgt");
+
+        let unknown = generator("foo:1 byr:1800");
+        let report = schema.validate(&unknown[0]);
+        assert_eq!(report.missing.len(), 6);
+        assert_eq!(report.unknown, vec!["foo"]);
+        assert_eq!(report.invalid.len(), 1);
+
+        for p in generator(VALID).iter() {
+            assert_eq!(schema.validate(p), Report::default());
+            assert_eq!(schema.validate(p).to_string(), "valid");
+        }
+    }
+
+    #[test]
+    pub fn test_normalize() {
+        let schema = Schema::default();
+        let passports = generator(SAMPLE);
+
+        assert_eq!(
+            schema.normalize(&passports[0]),
+            "byr:1937 iyr:2017 eyr:2020 hgt:183cm hcl:#fffffd ecl:gry pid:860033327 cid:147"
+        );
+        assert_eq!(
+            passports[3].to_string(),
+            "ecl:brn eyr:2025 hcl:#cfa07d hgt:59in iyr:2011 pid:166559648"
+        );
+
+        let unknown = generator("zzz:1 pid:1 aaa:2");
+        assert_eq!(schema.normalize(&unknown[0]), "pid:1 aaa:2 zzz:1");
+    }
+
+    #[test]
+    pub fn test_round_trip() {
+        for input in [SAMPLE, VALID, INVALID] {
+            let passports = generator(input);
+
+            assert_eq!(generator(&to_batch(&passports)), passports);
+            assert_eq!(
+                generator(&Schema::default().to_batch(&passports)),
+                passports
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_tolerant_parsing() {
+        let messy = format!(
+            "  \r\n{}\r\n \t \r\n\r\n\r\n",
+            SAMPLE
+                .replace('\n', "  \r\n")
+                .replace("  \r\n  \r\n", "\r\n \t\r\n")
+        );
+
+        assert_eq!(parse_batch(&messy), parse_batch(SAMPLE));
+        assert_eq!(generator(&messy), generator(SAMPLE));
+        assert!(parse_batch(&messy).issues.is_empty());
+    }
+
+    #[test]
+    pub fn test_parse_issues() {
+        let input = "a:1 b:x:y\n\nbyr:1 c\nbyr:2\n\n\nd:4 :5 e:\n\nf:6\n\ng:7\ng:8";
+        let batch = parse_batch(input);
+
+        assert_eq!(
+            batch
+                .passports
+                .iter()
+                .map(|p| p.to_string())
+                .collect::<Vec<_>>(),
+            vec!["a:1 b:x:y", "f:6", "g:7"]
+        );
+        assert_eq!(
+            batch.issues,
+            vec![
+                RecordIssues {
+                    record: 1,
+                    line: 3,
+                    issues: vec![
+                        ParseIssue {
+                            line: 3,
+                            column: 7,
+                            kind: IssueKind::Malformed("c".to_owned())
+                        },
+                        ParseIssue {
+                            line: 4,
+                            column: 1,
+                            kind: IssueKind::Duplicate("byr".to_owned())
+                        },
+                    ]
+                },
+                RecordIssues {
+                    record: 2,
+                    line: 7,
+                    issues: vec![
+                        ParseIssue {
+                            line: 7,
+                            column: 5,
+                            kind: IssueKind::Malformed(":5".to_owned())
+                        },
+                        ParseIssue {
+                            line: 7,
+                            column: 8,
+                            kind: IssueKind::Malformed("e:".to_owned())
+                        },
+                    ]
+                },
+                RecordIssues {
+                    record: 4,
+                    line: 11,
+                    issues: vec![ParseIssue {
+                        line: 12,
+                        column: 1,
+                        kind: IssueKind::Duplicate("g".to_owned())
+                    }]
+                },
+            ]
+        );
+        assert_eq!(
+            batch.issues[1].issues[0].to_string(),
+            "line 7, column 5: malformed field ':5'"
+        );
+        assert_eq!(generator(input).len(), 3);
+        assert!(parse_batch("").passports.is_empty());
+        assert!(parse_batch(" \n\n  ").issues.is_empty());
     }
 
     mod regression {
@@ -209,8 +982,8 @@ pid:3556412378 byr:2007";
         #[test]
         pub fn test() {
             let input = INPUT.trim_end_matches('\n'); // Trims trailing newline
-            assert_eq!(part1(&generator(input).unwrap()), ANSWERS.0);
-            assert_eq!(part2(&generator(input).unwrap()), ANSWERS.1);
+            assert_eq!(part1(&generator(input)), ANSWERS.0);
+            assert_eq!(part2(&generator(input)), ANSWERS.1);
         }
     }
 }
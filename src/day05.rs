@@ -1,41 +1,246 @@
+use std::ops::Range;
+
+// A boarding pass is `row_bits` letters from `row` followed by `column_bits`
+// letters from `column`; in each alphabet the first letter is 0 and the second 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SeatCode {
+    row_bits: u32,
+    column_bits: u32,
+    row: [char; 2],
+    column: [char; 2],
+}
+
+impl Default for SeatCode {
+    fn default() -> Self {
+        Self {
+            row_bits: 7,
+            column_bits: 3,
+            row: ['F', 'B'],
+            column: ['L', 'R'],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Seat {
+    id: usize,
+    column_bits: u32,
+}
+
+impl Seat {
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn row(&self) -> usize {
+        self.id >> self.column_bits
+    }
+
+    pub fn column(&self) -> usize {
+        self.id & ((1 << self.column_bits) - 1)
+    }
+}
+
+impl SeatCode {
+    // Returns `None` if the seat ids would not fit in a `usize`, or if either
+    // alphabet uses the same letter for 0 and 1.
+    pub fn new(row_bits: u32, column_bits: u32, row: [char; 2], column: [char; 2]) -> Option<Self> {
+        if row_bits.checked_add(column_bits)? >= usize::BITS
+            || row[0] == row[1]
+            || column[0] == column[1]
+        {
+            return None;
+        }
+
+        Some(Self {
+            row_bits,
+            column_bits,
+            row,
+            column,
+        })
+    }
+
+    pub fn seats(&self) -> usize {
+        1 << (self.row_bits + self.column_bits)
+    }
+
+    pub fn seat(&self, id: usize) -> Option<Seat> {
+        (id < self.seats()).then_some(Seat {
+            id,
+            column_bits: self.column_bits,
+        })
+    }
+
+    pub fn seat_at(&self, row: usize, column: usize) -> Option<Seat> {
+        if row >> self.row_bits != 0 || column >> self.column_bits != 0 {
+            return None;
+        }
+
+        self.seat((row << self.column_bits) | column)
+    }
+
+    fn alphabets(&self) -> impl Iterator<Item = [char; 2]> + use<> {
+        std::iter::repeat_n(self.row, self.row_bits as usize)
+            .chain(std::iter::repeat_n(self.column, self.column_bits as usize))
+    }
+
+    pub fn decode(&self, pass: &str) -> Option<Seat> {
+        let mut chars = pass.chars();
+        let id = self.alphabets().try_fold(0, |id, [zero, one]| {
+            let bit = match chars.next()? {
+                c if c == one => true,
+                c if c == zero => false,
+                _ => return None,
+            };
+
+            Some(id * 2 + bool2num(bit))
+        })?;
+
+        if chars.next().is_some() {
+            return None;
+        }
+
+        self.seat(id)
+    }
+
+    pub fn encode(&self, seat: Seat) -> Option<String> {
+        let seat = self.seat(seat.id)?;
+        let bits = self.row_bits + self.column_bits;
+
+        Some(
+            self.alphabets()
+                .enumerate()
+                .map(|(i, alphabet)| alphabet[(seat.id >> (bits as usize - 1 - i)) & 1])
+                .collect(),
+        )
+    }
+
+    pub fn pass(&self, id: usize) -> Option<String> {
+        self.encode(self.seat(id)?)
+    }
+}
+
 fn bool2num(b: bool) -> usize {
     b.into()
 }
 
+// Returns `None` if any line is not a valid boarding pass.
 #[aoc_generator(day5)]
-pub fn generator(input: &str) -> Vec<usize> {
+pub fn generator(input: &str) -> Option<Vec<usize>> {
+    let code = SeatCode::default();
+
     input
         .lines()
-        .map(|line| {
-            line.bytes()
-                .fold(0, |total, c| total * 2 + bool2num(c == b'B' || c == b'R'))
-        })
+        .map(|line| code.decode(line).map(|seat| seat.id()))
         .collect()
 }
 
+// The seats before the first and after the last taken one are always contiguous,
+// so they are kept as ranges rather than listed.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Vacancies {
+    pub front: Range<usize>,
+    pub interior: Vec<usize>,
+    pub back: Range<usize>,
+    pub duplicates: Vec<usize>,
+    pub out_of_range: Vec<usize>,
+}
+
+impl Vacancies {
+    // Interior vacancies with both neighbours taken could be our seat.
+    pub fn candidates(&self) -> impl Iterator<Item = usize> + '_ {
+        let missing = |id| self.interior.binary_search(&id).is_ok();
+
+        self.interior
+            .iter()
+            .copied()
+            .filter(move |&id| !missing(id - 1) && !missing(id + 1))
+    }
+
+    pub fn my_seat(&self) -> Option<usize> {
+        let mut candidates = self.candidates();
+
+        candidates.next().filter(|_| candidates.next().is_none())
+    }
+}
+
+// Ids that are not seats on the plane described by `code` are reported as out of
+// range and otherwise ignored. With no passes at all, every seat counts as missing
+// at the front.
+pub fn analyze(code: &SeatCode, ids: &[usize]) -> Vacancies {
+    let seats = code.seats();
+    let (mut taken, mut out_of_range): (Vec<_>, Vec<_>) = ids.iter().partition(|&&id| id < seats);
+    out_of_range.sort_unstable();
+    out_of_range.dedup();
+    taken.sort_unstable();
+
+    let mut duplicates = taken
+        .windows(2)
+        .filter(|pair| pair[0] == pair[1])
+        .map(|pair| pair[0])
+        .collect::<Vec<_>>();
+    duplicates.dedup();
+    taken.dedup();
+
+    let (Some(&first), Some(&last)) = (taken.first(), taken.last()) else {
+        return Vacancies {
+            front: 0..seats,
+            back: seats..seats,
+            out_of_range,
+            ..Vacancies::default()
+        };
+    };
+
+    Vacancies {
+        front: 0..first,
+        interior: taken
+            .windows(2)
+            .flat_map(|pair| pair[0] + 1..pair[1])
+            .collect(),
+        back: last + 1..seats,
+        duplicates,
+        out_of_range,
+    }
+}
+
+// One line per row: `#` for a taken seat, `.` for an empty one and `@` for the
+// seat `my_seat` would pick. Out-of-range ids are not drawn.
+pub fn render(code: &SeatCode, ids: &[usize]) -> String {
+    let mut plane = vec![b'.'; code.seats()];
+    for &id in ids {
+        if let Some(seat) = plane.get_mut(id) {
+            *seat = b'#';
+        }
+    }
+    if let Some(id) = analyze(code, ids).my_seat() {
+        plane[id] = b'@';
+    }
+
+    plane
+        .chunks(code.seats() >> code.row_bits)
+        .map(|row| String::from_utf8_lossy(row))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 #[aoc(day5, part1)]
 pub fn part1(inputs: &[usize]) -> usize {
     inputs.iter().copied().max().unwrap()
 }
 
 #[aoc(day5 part2, vec)]
-pub fn part2_vec(inputs: &[usize]) -> usize {
+pub fn part2_vec(inputs: &[usize]) -> Option<usize> {
     let mut set = inputs.to_vec();
     set.sort_unstable();
 
-    for seats in set.windows(2) {
-        assert!(seats.len() == 2);
-        if seats[0] + 1 != seats[1] {
-            return seats[0] + 1;
-        }
-    }
-
-    unreachable!()
+    set.windows(2)
+        .find(|seats| seats[0] + 1 != seats[1])
+        .map(|seats| seats[0] + 1)
 }
 
 #[aoc(day5 part2, array)]
 pub fn part2(inputs: &[usize]) -> usize {
-    let mut seats = [false; 1024];
+    let mut seats = vec![false; SeatCode::default().seats()];
 
     for id in inputs.iter().copied() {
         seats[id] = true
@@ -48,6 +253,11 @@ pub fn part2(inputs: &[usize]) -> usize {
     iter.next().unwrap().0 - 1
 }
 
+#[aoc(day5, part2, analysis)]
+pub fn part2_analysis(inputs: &[usize]) -> Option<usize> {
+    analyze(&SeatCode::default(), inputs).my_seat()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -59,17 +269,139 @@ BBFFBBFRLL";
 
     #[test]
     pub fn test_input() {
-        assert_eq!(generator(SAMPLE), vec![357, 567, 119, 820]);
+        assert_eq!(generator(SAMPLE), Some(vec![357, 567, 119, 820]));
+        assert_eq!(generator("FBFBBFFRLR\nFBFBBFFRLX"), None);
+        assert_eq!(generator("FBFBBFFRLR\nFBFBBFF"), None);
     }
 
     #[test]
     pub fn test1() {
-        assert_eq!(part1(&generator(SAMPLE)), 820);
+        assert_eq!(part1(&generator(SAMPLE).unwrap()), 820);
     }
 
     #[test]
     pub fn test2() {
-        assert_eq!(part2(&generator(SAMPLE)), 120);
+        assert_eq!(part2(&generator(SAMPLE).unwrap()), 120);
+        assert_eq!(part2_vec(&generator(SAMPLE).unwrap()), Some(120));
+        assert_eq!(part2_vec(&[3, 4, 5]), None);
+        assert_eq!(part2_vec(&[]), None);
+    }
+
+    #[test]
+    pub fn test_seat_code() {
+        let code = SeatCode::default();
+        let seat = code.decode("FBFBBFFRLR").unwrap();
+
+        assert_eq!((seat.row(), seat.column(), seat.id()), (44, 5, 357));
+        assert_eq!(code.seat_at(44, 5), Some(seat));
+        assert_eq!(code.encode(seat).as_deref(), Some("FBFBBFFRLR"));
+        assert_eq!(code.seat_at(128, 0), None);
+        assert_eq!(code.seat(1024), None);
+        assert_eq!(code.pass(820).as_deref(), Some("BBFFBBFRLL"));
+        assert_eq!(code.pass(1024), None);
+
+        for pass in ["FBFBBFFRL", "FBFBBFFRLRR", "FBFBBFFRLB", "RBFBBFFRLR"] {
+            assert_eq!(code.decode(pass), None, "{pass}");
+        }
+    }
+
+    #[test]
+    pub fn test_new() {
+        assert_eq!(
+            SeatCode::new(7, 3, ['F', 'B'], ['L', 'R']),
+            Some(SeatCode::default())
+        );
+        assert_eq!(SeatCode::new(60, 4, ['F', 'B'], ['L', 'R']), None);
+        assert_eq!(SeatCode::new(u32::MAX, 1, ['F', 'B'], ['L', 'R']), None);
+        assert_eq!(SeatCode::new(7, 3, ['F', 'F'], ['L', 'R']), None);
+        assert_eq!(SeatCode::new(7, 3, ['F', 'B'], ['R', 'R']), None);
+    }
+
+    #[test]
+    pub fn test_round_trip() {
+        for code in [
+            SeatCode::default(),
+            SeatCode::new(3, 2, ['U', 'D'], ['<', '>']).unwrap(),
+            SeatCode::new(0, 4, ['F', 'B'], ['0', '1']).unwrap(),
+            SeatCode::new(5, 0, ['ä', 'ö'], ['L', 'R']).unwrap(),
+        ] {
+            for id in 0..code.seats() {
+                let seat = code.seat(id).unwrap();
+                let pass = code.encode(seat).unwrap();
+
+                assert_eq!(code.decode(&pass), Some(seat));
+                assert_eq!(code.seat_at(seat.row(), seat.column()), Some(seat));
+            }
+        }
+
+        let code = SeatCode::new(3, 2, ['U', 'D'], ['<', '>']).unwrap();
+        let seat = code.decode("DUD><").unwrap();
+        assert_eq!((seat.row(), seat.column(), seat.id()), (5, 2, 22));
+    }
+
+    #[test]
+    pub fn test_analyze() {
+        let code = SeatCode::default();
+        let vacancies = analyze(&code, &generator(SAMPLE).unwrap());
+
+        assert_eq!(vacancies.front, 0..119);
+        assert_eq!(vacancies.back, 821..1024);
+        assert_eq!(vacancies.interior.len(), 820 - 119 + 1 - 4);
+        assert!(vacancies.duplicates.is_empty());
+        assert_eq!(vacancies.my_seat(), None);
+
+        let code = SeatCode::new(2, 2, ['F', 'B'], ['L', 'R']).unwrap();
+        let vacancies = analyze(&code, &[2, 3, 4, 6, 7, 9, 10, 10]);
+        assert_eq!(
+            vacancies,
+            Vacancies {
+                front: 0..2,
+                interior: vec![5, 8],
+                back: 11..16,
+                duplicates: vec![10],
+                out_of_range: vec![],
+            }
+        );
+        assert_eq!(vacancies.candidates().collect::<Vec<_>>(), vec![5, 8]);
+        assert_eq!(vacancies.my_seat(), None);
+
+        let vacancies = analyze(&code, &[1, 2, 4, 5, 6, 8, 9, 15, 14, 3]);
+        assert_eq!(vacancies.interior, vec![7, 10, 11, 12, 13]);
+        assert_eq!(vacancies.my_seat(), Some(7));
+        assert_eq!(vacancies.back, 16..16);
+
+        let vacancies = analyze(&code, &[]);
+        assert_eq!(vacancies.front, 0..16);
+        assert!(vacancies.interior.is_empty() && vacancies.back.is_empty());
+
+        let vacancies = analyze(&code, &[2000, 1, 2, 4, 16, 3, 2000]);
+        assert_eq!(vacancies.out_of_range, vec![16, 2000]);
+        assert_eq!(vacancies.interior, vec![]);
+        assert_eq!(vacancies.back, 5..16);
+        assert!(vacancies.duplicates.is_empty());
+
+        assert_eq!(part2_analysis(&[]), None);
+
+        // The plane's size does not matter, only the passes seen.
+        let code = SeatCode::new(50, 10, ['F', 'B'], ['L', 'R']).unwrap();
+        let vacancies = analyze(&code, &[5, 7, 3, 1 << 60]);
+        assert_eq!(vacancies.front, 0..3);
+        assert_eq!(vacancies.interior, vec![4, 6]);
+        assert_eq!(vacancies.back, 8..1 << 60);
+        assert_eq!(vacancies.out_of_range, vec![1 << 60]);
+        assert_eq!(part2_analysis(&[2000]), None);
+    }
+
+    #[test]
+    pub fn test_render() {
+        let code = SeatCode::new(2, 2, ['F', 'B'], ['L', 'R']).unwrap();
+
+        assert_eq!(
+            render(&code, &[1, 2, 4, 5, 6, 8, 9, 15, 14, 3]),
+            ".###\n###@\n##..\n..##"
+        );
+        assert_eq!(render(&code, &[]), "....\n....\n....\n....");
+        assert_eq!(render(&code, &[0, 2000]), "#...\n....\n....\n....");
     }
 
     mod regression {
@@ -81,8 +413,9 @@ BBFFBBFRLL";
         #[test]
         pub fn test() {
             let input = INPUT.trim_end_matches('\n'); // Trims trailing newline
-            assert_eq!(part1(&generator(input)), ANSWERS.0);
-            assert_eq!(part2(&generator(input)), ANSWERS.1);
+            assert_eq!(part1(&generator(input).unwrap()), ANSWERS.0);
+            assert_eq!(part2(&generator(input).unwrap()), ANSWERS.1);
+            assert_eq!(part2_analysis(&generator(input).unwrap()), Some(ANSWERS.1));
         }
     }
 }
@@ -1,3 +1,82 @@
+use std::collections::BTreeMap;
+
+use bit_set::BitSet;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Alphabet(Vec<char>);
+
+impl Default for Alphabet {
+    fn default() -> Self {
+        Self(('a'..='z').collect())
+    }
+}
+
+impl Alphabet {
+    pub fn new(questions: &str) -> Self {
+        let mut alphabet = Vec::new();
+        for c in questions.chars() {
+            if !alphabet.contains(&c) {
+                alphabet.push(c);
+            }
+        }
+
+        Self(alphabet)
+    }
+
+    // Every question answered anywhere in `groups`, in sorted order.
+    pub fn from_groups(groups: &[Group]) -> Self {
+        let mut alphabet = groups
+            .iter()
+            .flat_map(|g| g.people.iter().flat_map(|p| p.chars()))
+            .collect::<Vec<_>>();
+        alphabet.sort_unstable();
+        alphabet.dedup();
+
+        Self(alphabet)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn index(&self, question: char) -> Option<usize> {
+        self.0.iter().position(|&c| c == question)
+    }
+
+    pub fn question(&self, index: usize) -> Option<char> {
+        self.0.get(index).copied()
+    }
+
+    pub fn decode(&self, questions: &BitSet) -> String {
+        questions.iter().filter_map(|i| self.question(i)).collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Quorum {
+    Any,
+    All,
+    AtLeast(usize),
+    Exactly(usize),
+    Majority,
+}
+
+impl Quorum {
+    fn accepts(&self, count: usize, people: usize) -> bool {
+        match *self {
+            Quorum::Any => count > 0,
+            Quorum::All => count == people,
+            Quorum::AtLeast(k) => count >= k,
+            Quorum::Exactly(k) => count == k,
+            Quorum::Majority => count * 2 > people,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct Group {
     people: Vec<String>,
@@ -10,17 +89,70 @@ impl Group {
         Self { people }
     }
 
-    pub fn count_vote<F: Fn(usize) -> bool>(&self, predicate: F) -> usize {
+    // Returns `None` if anyone answered something other than `a` to `z`; see
+    // `query` for other alphabets.
+    pub fn count_vote<F: Fn(usize) -> bool>(&self, predicate: F) -> Option<usize> {
         let mut tally = [0; 26];
 
         for person in self.people.iter() {
             for vote in person.bytes() {
+                if !vote.is_ascii_lowercase() {
+                    return None;
+                }
+
                 let idx = vote - b'a';
                 tally[usize::from(idx)] += 1;
             }
         }
 
-        tally.iter().filter(|&&n| predicate(n)).count()
+        Some(tally.iter().filter(|&&n| predicate(n)).count())
+    }
+
+    pub fn len(&self) -> usize {
+        self.people.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.people.is_empty()
+    }
+
+    // One bitset of question indices per person, or `None` if someone answered a
+    // question outside `alphabet`.
+    pub fn ballots(&self, alphabet: &Alphabet) -> Option<Vec<BitSet>> {
+        self.people
+            .iter()
+            .map(|person| person.chars().map(|c| alphabet.index(c)).collect())
+            .collect()
+    }
+
+    pub fn query(&self, alphabet: &Alphabet, quorum: Quorum) -> Option<BitSet> {
+        let ballots = self.ballots(alphabet)?;
+        let mut iter = ballots.iter();
+
+        match quorum {
+            Quorum::Any => Some(iter.fold(BitSet::new(), |mut acc, ballot| {
+                acc.union_with(ballot);
+                acc
+            })),
+            Quorum::All => Some(iter.next().map_or_else(BitSet::new, |first| {
+                iter.fold(first.clone(), |mut acc, ballot| {
+                    acc.intersect_with(ballot);
+                    acc
+                })
+            })),
+            _ => {
+                let mut tally = vec![0; alphabet.len()];
+                for question in ballots.iter().flat_map(|ballot| ballot.iter()) {
+                    tally[question] += 1;
+                }
+
+                Some(
+                    (0..alphabet.len())
+                        .filter(|&q| quorum.accepts(tally[q], self.people.len()))
+                        .collect(),
+                )
+            }
+        }
     }
 
     pub fn unique_votes_hs(&self) -> usize {
@@ -51,13 +183,126 @@ impl Group {
     }
 }
 
+// Aggregate answers across all groups. `frequency` counts people per question,
+// `group_frequency` counts groups in which anyone answered it, and
+// `co_occurrence[i][j]` counts people who answered both `i` and `j`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Survey {
+    alphabet: Alphabet,
+    pub people: usize,
+    pub sizes: BTreeMap<usize, usize>,
+    pub frequency: Vec<usize>,
+    pub group_frequency: Vec<usize>,
+    pub co_occurrence: Vec<Vec<usize>>,
+}
+
+impl Survey {
+    pub fn new(groups: &[Group], alphabet: &Alphabet) -> Option<Self> {
+        let n = alphabet.len();
+        let mut survey = Self {
+            alphabet: alphabet.clone(),
+            people: 0,
+            sizes: BTreeMap::new(),
+            frequency: vec![0; n],
+            group_frequency: vec![0; n],
+            co_occurrence: vec![vec![0; n]; n],
+        };
+
+        for group in groups {
+            survey.people += group.len();
+            *survey.sizes.entry(group.len()).or_insert(0) += 1;
+
+            for q in group.query(alphabet, Quorum::Any)?.iter() {
+                survey.group_frequency[q] += 1;
+            }
+
+            for ballot in group.ballots(alphabet)? {
+                for i in ballot.iter() {
+                    survey.frequency[i] += 1;
+                    for j in ballot.iter() {
+                        survey.co_occurrence[i][j] += 1;
+                    }
+                }
+            }
+        }
+
+        Some(survey)
+    }
+
+    pub fn groups(&self) -> usize {
+        self.sizes.values().sum()
+    }
+
+    pub fn never_answered(&self) -> String {
+        self.questions_where(|q| self.frequency[q] == 0)
+    }
+
+    // Questions every person in every group answered.
+    pub fn unanimous(&self) -> String {
+        self.questions_where(|q| self.people > 0 && self.frequency[q] == self.people)
+    }
+
+    fn questions_where(&self, predicate: impl Fn(usize) -> bool) -> String {
+        (0..self.alphabet.len())
+            .filter(|&q| predicate(q))
+            .filter_map(|q| self.alphabet.question(q))
+            .collect()
+    }
+
+    fn table(rows: impl Iterator<Item = Vec<String>>, separator: &str) -> String {
+        rows.map(|row| row.join(separator))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn label(&self, q: usize) -> String {
+        self.alphabet.question(q).unwrap().to_string()
+    }
+
+    pub fn frequency_table(&self, separator: &str) -> String {
+        let header = ["question", "people", "groups"].map(String::from).to_vec();
+        let rows = (0..self.alphabet.len()).map(|q| {
+            vec![
+                self.label(q),
+                self.frequency[q].to_string(),
+                self.group_frequency[q].to_string(),
+            ]
+        });
+
+        Self::table(std::iter::once(header).chain(rows), separator)
+    }
+
+    pub fn size_table(&self, separator: &str) -> String {
+        let header = ["size", "groups"].map(String::from).to_vec();
+        let rows = self
+            .sizes
+            .iter()
+            .map(|(size, count)| vec![size.to_string(), count.to_string()]);
+
+        Self::table(std::iter::once(header).chain(rows), separator)
+    }
+
+    pub fn co_occurrence_table(&self, separator: &str) -> String {
+        let header = std::iter::once(String::new())
+            .chain((0..self.alphabet.len()).map(|q| self.label(q)))
+            .collect();
+        let rows = self.co_occurrence.iter().enumerate().map(|(q, row)| {
+            std::iter::once(self.label(q))
+                .chain(row.iter().map(|n| n.to_string()))
+                .collect()
+        });
+
+        Self::table(std::iter::once(header).chain(rows), separator)
+    }
+}
+
 #[aoc_generator(day6)]
 pub fn generator(input: &str) -> Vec<Group> {
     input.split("\n\n").map(Group::new).collect()
 }
 
 #[aoc(day6, part1)]
-pub fn part1(inputs: &[Group]) -> usize {
+pub fn part1(inputs: &[Group]) -> Option<usize> {
     inputs
         .iter()
         .map(|group| group.count_vote(|ans| ans > 0))
@@ -65,7 +310,7 @@ pub fn part1(inputs: &[Group]) -> usize {
 }
 
 #[aoc(day6, part2)]
-pub fn part2(inputs: &[Group]) -> usize {
+pub fn part2(inputs: &[Group]) -> Option<usize> {
     inputs
         .iter()
         .map(|group| group.count_vote(|ans| ans == group.people.len()))
@@ -85,6 +330,26 @@ pub fn part2_hm(inputs: &[Group]) -> usize {
         .sum()
 }
 
+#[aoc(day6, part1, bitset)]
+pub fn part1_bitset(inputs: &[Group]) -> usize {
+    let alphabet = Alphabet::default();
+
+    inputs
+        .iter()
+        .map(|group| group.query(&alphabet, Quorum::Any).unwrap().len())
+        .sum()
+}
+
+#[aoc(day6, part2, bitset)]
+pub fn part2_bitset(inputs: &[Group]) -> usize {
+    let alphabet = Alphabet::default();
+
+    inputs
+        .iter()
+        .map(|group| group.query(&alphabet, Quorum::All).unwrap().len())
+        .sum()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -119,12 +384,101 @@ b";
 
     #[test]
     pub fn test1() {
-        assert_eq!(part1(&generator(SAMPLE)), 11);
+        assert_eq!(part1(&generator(SAMPLE)), Some(11));
     }
 
     #[test]
     pub fn test2() {
-        assert_eq!(part2(&generator(SAMPLE)), 6);
+        assert_eq!(part2(&generator(SAMPLE)), Some(6));
+    }
+
+    #[test]
+    pub fn test_bitset() {
+        assert_eq!(part1_bitset(&generator(SAMPLE)), 11);
+        assert_eq!(part2_bitset(&generator(SAMPLE)), 6);
+    }
+
+    #[test]
+    pub fn test_quorum() {
+        let alphabet = Alphabet::default();
+        let group = Group::new("abcx\nabd\nab\nbx\ncx");
+        let query = |quorum| alphabet.decode(&group.query(&alphabet, quorum).unwrap());
+
+        assert_eq!(query(Quorum::Any), "abcdx");
+        assert_eq!(query(Quorum::All), "");
+        assert_eq!(query(Quorum::AtLeast(3)), "abx");
+        assert_eq!(query(Quorum::AtLeast(4)), "b");
+        assert_eq!(query(Quorum::Exactly(2)), "c");
+        assert_eq!(query(Quorum::Exactly(0)), "efghijklmnopqrstuvwyz");
+        assert_eq!(query(Quorum::Majority), "abx");
+
+        let group = Group::new("ab\nb");
+        assert_eq!(
+            alphabet.decode(&group.query(&alphabet, Quorum::Majority).unwrap()),
+            "b"
+        );
+        assert_eq!(
+            Group::new("").query(&alphabet, Quorum::All),
+            Some(BitSet::new())
+        );
+    }
+
+    #[test]
+    pub fn test_alphabet() {
+        let alphabet = Alphabet::new("XYZ🎄");
+        let group = Group::new("X🎄\n🎄Z\n🎄");
+
+        assert_eq!(alphabet.len(), 4);
+        assert_eq!(
+            alphabet.decode(&group.query(&alphabet, Quorum::All).unwrap()),
+            "🎄"
+        );
+        assert_eq!(
+            alphabet.decode(&group.query(&alphabet, Quorum::Any).unwrap()),
+            "XZ🎄"
+        );
+        assert_eq!(Group::new("XA").query(&alphabet, Quorum::Any), None);
+        assert_eq!(Group::new("xA").count_vote(|n| n > 0), None);
+        assert_eq!(Group::new("xy\nx").count_vote(|n| n > 1), Some(1));
+        assert_eq!(part1(&generator("ab\n\nc🎄")), None);
+
+        let groups = generator("ba\n\nQ");
+        assert_eq!(Alphabet::from_groups(&groups), Alphabet::new("Qab"));
+    }
+
+    #[test]
+    pub fn test_survey() {
+        let groups = generator(SAMPLE);
+        let survey = Survey::new(&groups, &Alphabet::new("abcd")).unwrap();
+
+        assert_eq!(survey.people, 11);
+        assert_eq!(survey.groups(), 5);
+        assert_eq!(
+            survey.sizes,
+            BTreeMap::from([(1, 2), (2, 1), (3, 1), (4, 1)])
+        );
+        assert_eq!(survey.frequency, vec![8, 4, 3, 0]);
+        assert_eq!(survey.group_frequency, vec![4, 4, 3, 0]);
+        assert_eq!(survey.never_answered(), "d");
+        assert_eq!(survey.unanimous(), "");
+        assert_eq!(
+            survey.frequency_table(","),
+            "question,people,groups\na,8,4\nb,4,4\nc,3,3\nd,0,0"
+        );
+        assert_eq!(
+            survey.size_table("\t"),
+            "size\tgroups\n1\t2\n2\t1\n3\t1\n4\t1"
+        );
+        assert_eq!(
+            survey.co_occurrence_table(","),
+            ",a,b,c,d\na,8,2,2,0\nb,2,4,1,0\nc,2,1,3,0\nd,0,0,0,0"
+        );
+
+        let survey = Survey::new(&generator("ab\nba\n\nb"), &Alphabet::default()).unwrap();
+        assert_eq!(survey.unanimous(), "b");
+        assert_eq!(survey.never_answered().len(), 24);
+
+        assert_eq!(Survey::new(&groups, &Alphabet::new("ab")), None);
     }
 
     mod regression {
@@ -136,8 +490,8 @@ b";
         #[test]
         pub fn test() {
             let input = INPUT.trim_end_matches('\n'); // Trims trailing newline
-            assert_eq!(part1(&generator(input)), ANSWERS.0);
-            assert_eq!(part2(&generator(input)), ANSWERS.1);
+            assert_eq!(part1(&generator(input)), Some(ANSWERS.0));
+            assert_eq!(part2(&generator(input)), Some(ANSWERS.1));
         }
     }
 }
@@ -1,27 +1,718 @@
-use std::collections::HashMap;
+use std::{
+    cmp::Reverse,
+    collections::{BTreeSet, BinaryHeap, HashMap},
+    fmt,
+};
 
-#[derive(Debug, PartialEq, Eq)]
+use num::{BigUint, CheckedAdd, CheckedMul, One, Zero};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct BagRules {
     rules: HashMap<BagColor, Vec<(BagColor, usize)>>,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BagError {
+    // The colors along the cycle, starting and ending with the same color.
+    Cycle(Vec<BagColor>),
+    Overflow(BagColor),
+    Parse(String),
+}
+
+impl fmt::Display for BagError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BagError::Cycle(colors) => write!(f, "bag cycle: {}", colors.join(" -> ")),
+            BagError::Overflow(color) => write!(f, "bag count overflows inside {color}"),
+            BagError::Parse(line) => write!(f, "invalid rule: {line}"),
+        }
+    }
+}
+
+impl std::error::Error for BagError {}
+
+// Memoized DFS state: `None` marks a color that is still being expanded.
+type Memo<'a, T> = HashMap<&'a str, Option<T>>;
+type Children<'a, T> = Vec<(&'a str, usize, T)>;
+
 impl BagRules {
-    fn contains(&self, needle: &str, target: &str) -> bool {
-        self.rules[needle]
-            .iter()
-            .any(|(color, _)| color == target || self.contains(color, target))
+    // Post-order DFS from `color`, combining each color's children once their
+    // values are known. Reaching a color that is still being expanded is a cycle.
+    // The walk keeps its own stack of (color, next child) frames, so deeply
+    // nested rules cannot overflow the call stack.
+    fn memoized<'a, T: Clone>(
+        &'a self,
+        color: &'a str,
+        memo: &mut Memo<'a, T>,
+        combine: &impl Fn(&'a str, Children<'a, T>) -> Result<T, BagError>,
+    ) -> Result<T, BagError> {
+        let children = |color: &str| self.rules.get(color).map_or(&[][..], Vec::as_slice);
+        let mut stack: Vec<(&'a str, usize)> = Vec::new();
+        let visit =
+            |color: &'a str, memo: &mut Memo<'a, T>, stack: &mut Vec<_>| match memo.get(color) {
+                Some(Some(_)) => Ok(()),
+                Some(None) => {
+                    let start = stack.iter().position(|&(c, _)| c == color).unwrap();
+                    let cycle = stack[start..].iter().map(|&(c, _)| c).chain([color]);
+                    Err(BagError::Cycle(cycle.map(str::to_owned).collect()))
+                }
+                None => {
+                    memo.insert(color, None);
+                    stack.push((color, 0));
+                    Ok(())
+                }
+            };
+
+        visit(color, memo, &mut stack)?;
+        while let Some((current, next)) = stack.last_mut() {
+            let current = *current;
+            let list = children(current);
+
+            if let Some((child, _)) = list.get(*next) {
+                *next += 1;
+                visit(child, memo, &mut stack)?;
+                continue;
+            }
+
+            stack.pop();
+            let values = list
+                .iter()
+                .map(|(c, n)| (c.as_str(), *n, memo[c.as_str()].clone().unwrap()))
+                .collect();
+            let value = combine(current, values)?;
+            memo.insert(current, Some(value));
+        }
+
+        Ok(memo[color].clone().unwrap())
+    }
+
+    pub fn check_acyclic(&self) -> Result<(), BagError> {
+        let mut memo = HashMap::new();
+        let combine = |_, _| Ok(());
+
+        self.colors()
+            .into_iter()
+            .try_for_each(|color| self.memoized(color, &mut memo, &combine))
+    }
+
+    pub fn contains(&self, needle: &str, target: &str) -> Result<bool, BagError> {
+        self.memoized(needle, &mut HashMap::new(), &Self::contains_target(target))
+    }
+
+    fn contains_target<'a>(
+        target: &str,
+    ) -> impl Fn(&'a str, Children<'a, bool>) -> Result<bool, BagError> {
+        move |_, children| Ok(children.iter().any(|&(c, _, found)| found || c == target))
+    }
+
+    // Number of colors that eventually contain `target`, sharing one memo table.
+    pub fn count_holders(&self, target: &str) -> Result<usize, BagError> {
+        let mut memo = HashMap::new();
+        let combine = Self::contains_target(target);
+
+        self.rules.keys().try_fold(0, |n, color| {
+            let found = self.memoized(color, &mut memo, &combine)?;
+            Ok(n + usize::from(found))
+        })
+    }
+
+    pub fn count_contained(&self, color: &str) -> Result<usize, BagError> {
+        self.count_with(color)
+    }
+
+    pub fn count_contained_big(&self, color: &str) -> Result<BigUint, BagError> {
+        self.count_with(color)
+    }
+
+    fn count_with<T>(&self, color: &str) -> Result<T, BagError>
+    where
+        T: Clone + Zero + One + CheckedAdd + CheckedMul + From<usize>,
+    {
+        let combine = |color: &str, children: Children<'_, T>| {
+            children
+                .into_iter()
+                .try_fold(T::zero(), |total, (_, count, inner)| {
+                    inner
+                        .checked_add(&T::one())
+                        .and_then(|bags| T::from(count).checked_mul(&bags))
+                        .and_then(|bags| total.checked_add(&bags))
+                        .ok_or_else(|| BagError::Overflow(color.to_owned()))
+                })
+        };
+
+        self.memoized(color, &mut HashMap::new(), &combine)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scope<'a> {
+    All,
+    ReachableFrom(&'a str),
+    Reaching(&'a str),
+}
+
+impl BagRules {
+    fn children(&self, color: &str) -> impl Iterator<Item = &(BagColor, usize)> {
+        self.rules.get(color).into_iter().flatten()
     }
 
-    fn count_contained(&self, color: &str) -> usize {
-        self.rules[color]
+    fn colors(&self) -> BTreeSet<&str> {
+        self.rules
             .iter()
-            .map(|(c, count)| count * (self.count_contained(c) + 1))
-            .sum::<usize>()
+            .flat_map(|(color, children)| {
+                std::iter::once(color).chain(children.iter().map(|(c, _)| c))
+            })
+            .map(String::as_str)
+            .collect()
+    }
+
+    // `start` plus every color reachable by following `next`.
+    fn closure<'a, I>(&'a self, start: &'a str, next: impl Fn(&'a str) -> I) -> BTreeSet<&'a str>
+    where
+        I: Iterator<Item = &'a str>,
+    {
+        let mut seen = BTreeSet::from([start]);
+        let mut stack = vec![start];
+
+        while let Some(color) = stack.pop() {
+            for c in next(color) {
+                if seen.insert(c) {
+                    stack.push(c);
+                }
+            }
+        }
+
+        seen
+    }
+
+    pub fn to_dot(&self, scope: Scope) -> String {
+        let nodes = match scope {
+            Scope::All => self.colors(),
+            Scope::ReachableFrom(color) => {
+                self.closure(color, |c| self.children(c).map(|(c, _)| c.as_str()))
+            }
+            Scope::Reaching(color) => self.closure(color, |c| {
+                self.rules
+                    .iter()
+                    .filter(move |(_, children)| children.iter().any(|(child, _)| child == c))
+                    .map(|(parent, _)| parent.as_str())
+            }),
+        };
+
+        let quote = |color: &str| format!("\"{}\"", color.replace('"', "\\\""));
+        let mut dot = String::from("digraph bags {\n");
+
+        for color in nodes.iter() {
+            dot.push_str(&format!("    {};\n", quote(color)));
+        }
+
+        for color in nodes.iter() {
+            let mut edges = self
+                .children(color)
+                .filter(|(child, _)| nodes.contains(child.as_str()))
+                .collect::<Vec<_>>();
+            edges.sort();
+
+            for (child, count) in edges {
+                dot.push_str(&format!(
+                    "    {} -> {} [label=\"{count}\"];\n",
+                    quote(color),
+                    quote(child)
+                ));
+            }
+        }
+
+        dot.push('}');
+        dot
     }
 }
 
 type BagColor = String;
 
+const TARGET: &str = "shiny gold";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainmentPath<'a> {
+    pub colors: Vec<&'a str>,
+    pub count: usize,
+}
+
+// The rule graph indexed both ways. Colors are numbered in sorted order, and
+// construction rejects cyclic rule sets, so every query can assume a DAG.
+#[derive(Debug)]
+pub struct BagGraph {
+    colors: Vec<BagColor>,
+    index: HashMap<BagColor, usize>,
+    children: Vec<Vec<(usize, usize)>>,
+    parents: Vec<Vec<(usize, usize)>>,
+    order: Vec<usize>,
+}
+
+impl TryFrom<&BagRules> for BagGraph {
+    type Error = BagError;
+
+    fn try_from(rules: &BagRules) -> Result<Self, Self::Error> {
+        let colors = rules
+            .colors()
+            .into_iter()
+            .map(String::from)
+            .collect::<Vec<_>>();
+        let index = colors
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (c.clone(), i))
+            .collect::<HashMap<_, _>>();
+
+        let mut children = vec![Vec::new(); colors.len()];
+        let mut parents = vec![Vec::new(); colors.len()];
+        for (parent, list) in rules.rules.iter() {
+            for (child, count) in list {
+                children[index[parent]].push((index[child], *count));
+                parents[index[child]].push((index[parent], *count));
+            }
+        }
+
+        // Kahn's algorithm, always taking the alphabetically first ready color.
+        let mut indegree = parents.iter().map(Vec::len).collect::<Vec<_>>();
+        let mut ready = (0..colors.len())
+            .filter(|&i| indegree[i] == 0)
+            .map(Reverse)
+            .collect::<BinaryHeap<_>>();
+        let mut order = Vec::with_capacity(colors.len());
+
+        while let Some(Reverse(i)) = ready.pop() {
+            order.push(i);
+            for &(child, _) in children[i].iter() {
+                indegree[child] -= 1;
+                if indegree[child] == 0 {
+                    ready.push(Reverse(child));
+                }
+            }
+        }
+
+        // Colors on a cycle, or below one, never become ready. Each of them still
+        // has a parent that was never placed either, so walking up through such
+        // parents must come back to a color already seen, closing a cycle.
+        if order.len() < colors.len() {
+            let stuck = |i: usize| indegree[i] > 0;
+            let mut seen = vec![None; colors.len()];
+            let mut walk = Vec::new();
+            let mut i = (0..colors.len()).find(|&i| stuck(i)).unwrap();
+            while seen[i].is_none() {
+                seen[i] = Some(walk.len());
+                walk.push(i);
+                i = parents[i]
+                    .iter()
+                    .map(|&(p, _)| p)
+                    .filter(|&p| stuck(p))
+                    .min()
+                    .unwrap();
+            }
+
+            // The walk runs from child to parent; report it from the first color
+            // of the cycle downwards.
+            let mut cycle = walk.split_off(seen[i].unwrap());
+            cycle.reverse();
+            let first = (0..cycle.len()).min_by_key(|&k| cycle[k]).unwrap();
+            cycle.rotate_left(first);
+            cycle.push(cycle[0]);
+
+            return Err(BagError::Cycle(
+                cycle.into_iter().map(|i| colors[i].clone()).collect(),
+            ));
+        }
+
+        Ok(Self {
+            colors,
+            index,
+            children,
+            parents,
+            order,
+        })
+    }
+}
+
+impl BagGraph {
+    fn reach(&self, color: &str, edges: &[Vec<(usize, usize)>]) -> BTreeSet<&str> {
+        let mut seen = BTreeSet::new();
+        let mut stack = self
+            .index
+            .get(color)
+            .into_iter()
+            .copied()
+            .collect::<Vec<_>>();
+
+        while let Some(i) = stack.pop() {
+            for &(next, _) in edges[i].iter() {
+                if seen.insert(self.colors[next].as_str()) {
+                    stack.push(next);
+                }
+            }
+        }
+
+        seen
+    }
+
+    // Every color that eventually contains `color`.
+    pub fn ancestors(&self, color: &str) -> BTreeSet<&str> {
+        self.reach(color, &self.parents)
+    }
+
+    // Every color eventually contained in `color`.
+    pub fn descendants(&self, color: &str) -> BTreeSet<&str> {
+        self.reach(color, &self.children)
+    }
+
+    // Outer bags come before the bags they contain.
+    pub fn topological_order(&self) -> Vec<&str> {
+        self.order
+            .iter()
+            .map(|&i| self.colors[i].as_str())
+            .collect()
+    }
+
+    // How many bags of each color one `outer` bag holds in total, nested
+    // arbitrarily deep, found by pushing counts forward in topological order.
+    fn counts(&self, outer: &str) -> Result<Vec<usize>, BagError> {
+        let mut counts = vec![0_usize; self.colors.len()];
+        let Some(&start) = self.index.get(outer) else {
+            return Ok(counts);
+        };
+        counts[start] = 1;
+
+        for &i in self.order.iter().skip_while(|&&i| i != start) {
+            for &(child, count) in self.children[i].iter() {
+                counts[child] = counts[i]
+                    .checked_mul(count)
+                    .and_then(|n| n.checked_add(counts[child]))
+                    .ok_or_else(|| BagError::Overflow(outer.to_owned()))?;
+            }
+        }
+        counts[start] = 0;
+
+        Ok(counts)
+    }
+
+    pub fn multiplicity(&self, outer: &str, inner: &str) -> Result<usize, BagError> {
+        let counts = self.counts(outer)?;
+        Ok(self.index.get(inner).map_or(0, |&i| counts[i]))
+    }
+
+    pub fn total_contained(&self, outer: &str) -> Result<usize, BagError> {
+        self.counts(outer)?
+            .into_iter()
+            .try_fold(0_usize, |total, n| total.checked_add(n))
+            .ok_or_else(|| BagError::Overflow(outer.to_owned()))
+    }
+
+    // Every chain of containment from `outer` down to `inner`, with the number of
+    // `inner` bags it contributes.
+    pub fn paths(&self, outer: &str, inner: &str) -> Result<Vec<ContainmentPath<'_>>, BagError> {
+        let (Some(&from), Some(&to)) = (self.index.get(outer), self.index.get(inner)) else {
+            return Ok(Vec::new());
+        };
+        let relevant = self.ancestors(inner);
+        let mut paths = Vec::new();
+        let mut stack = vec![(vec![from], 1_usize)];
+
+        while let Some((path, count)) = stack.pop() {
+            let last = *path.last().unwrap();
+            if last == to && path.len() > 1 {
+                paths.push(ContainmentPath {
+                    colors: path.iter().map(|&i| self.colors[i].as_str()).collect(),
+                    count,
+                });
+                continue;
+            }
+
+            for &(child, n) in self.children[last].iter().rev() {
+                if child == to || relevant.contains(self.colors[child].as_str()) {
+                    let count = count
+                        .checked_mul(n)
+                        .ok_or_else(|| BagError::Overflow(outer.to_owned()))?;
+                    let mut path = path.clone();
+                    path.push(child);
+                    stack.push((path, count));
+                }
+            }
+        }
+
+        Ok(paths)
+    }
+}
+
+impl BagRules {
+    pub fn rule(&self, color: &str) -> Option<&[(BagColor, usize)]> {
+        self.rules.get(color).map(Vec::as_slice)
+    }
+
+    // Replaces the rule for `color`, returning the previous contents.
+    pub fn set_rule(
+        &mut self,
+        color: &str,
+        contents: Vec<(BagColor, usize)>,
+    ) -> Option<Vec<(BagColor, usize)>> {
+        self.rules.insert(color.to_owned(), contents)
+    }
+
+    pub fn remove_rule(&mut self, color: &str) -> Option<Vec<(BagColor, usize)>> {
+        self.rules.remove(color)
+    }
+
+    fn colors_owned(&self) -> BTreeSet<BagColor> {
+        self.colors().into_iter().map(String::from).collect()
+    }
+}
+
+// `BagRules` plus cached `ancestors` and `count_contained` answers. Editing the
+// rule for one color only drops the cached answers that edit can change: counts
+// for the color and everything holding it, and ancestor sets for everything it
+// held before or holds now.
+#[derive(Debug)]
+pub struct IncrementalRules {
+    rules: BagRules,
+    parents: HashMap<BagColor, BTreeSet<BagColor>>,
+    ancestors: HashMap<BagColor, BTreeSet<BagColor>>,
+    counts: HashMap<BagColor, usize>,
+}
+
+impl TryFrom<BagRules> for IncrementalRules {
+    type Error = BagError;
+
+    fn try_from(rules: BagRules) -> Result<Self, Self::Error> {
+        rules.check_acyclic()?;
+
+        let mut parents = HashMap::<_, BTreeSet<_>>::new();
+        for (parent, children) in rules.rules.iter() {
+            for (child, _) in children {
+                parents
+                    .entry(child.clone())
+                    .or_default()
+                    .insert(parent.clone());
+            }
+        }
+
+        Ok(Self {
+            rules,
+            parents,
+            ancestors: HashMap::new(),
+            counts: HashMap::new(),
+        })
+    }
+}
+
+impl IncrementalRules {
+    pub fn rules(&self) -> &BagRules {
+        &self.rules
+    }
+
+    pub fn is_cached(&self, color: &str) -> (bool, bool) {
+        (
+            self.ancestors.contains_key(color),
+            self.counts.contains_key(color),
+        )
+    }
+
+    fn closure(&self, color: &str, next: impl Fn(&str) -> Vec<BagColor>) -> BTreeSet<BagColor> {
+        let mut seen = BTreeSet::new();
+        let mut stack = next(color);
+
+        while let Some(c) = stack.pop() {
+            if seen.insert(c.clone()) {
+                stack.extend(next(&c));
+            }
+        }
+
+        seen
+    }
+
+    fn uncached_ancestors(&self, color: &str) -> BTreeSet<BagColor> {
+        self.closure(color, |c| {
+            self.parents.get(c).into_iter().flatten().cloned().collect()
+        })
+    }
+
+    fn descendants(&self, color: &str) -> BTreeSet<BagColor> {
+        self.closure(color, |c| {
+            self.rules.children(c).map(|(c, _)| c.clone()).collect()
+        })
+    }
+
+    pub fn ancestors(&mut self, color: &str) -> &BTreeSet<BagColor> {
+        if !self.ancestors.contains_key(color) {
+            let ancestors = self.uncached_ancestors(color);
+            self.ancestors.insert(color.to_owned(), ancestors);
+        }
+
+        &self.ancestors[color]
+    }
+
+    pub fn count_contained(&mut self, color: &str) -> Result<usize, BagError> {
+        if let Some(&count) = self.counts.get(color) {
+            return Ok(count);
+        }
+
+        // Seed the DFS with everything already cached and keep whatever it
+        // computes along the way.
+        let mut memo: Memo<'_, usize> = self
+            .counts
+            .iter()
+            .map(|(c, &n)| (c.as_str(), Some(n)))
+            .collect();
+        let combine = |color: &str, children: Children<'_, usize>| {
+            children
+                .into_iter()
+                .try_fold(0_usize, |total, (_, count, inner)| {
+                    count
+                        .checked_mul(inner + 1)
+                        .and_then(|bags| total.checked_add(bags))
+                        .ok_or_else(|| BagError::Overflow(color.to_owned()))
+                })
+        };
+        let count = self.rules.memoized(color, &mut memo, &combine)?;
+        let computed = memo
+            .into_iter()
+            .filter_map(|(c, n)| Some((c.to_owned(), n?)))
+            .collect::<Vec<_>>();
+
+        self.counts.extend(computed);
+        Ok(count)
+    }
+
+    // Rejects (and rolls back) edits that would make the rules cyclic.
+    pub fn set_rule(
+        &mut self,
+        color: &str,
+        contents: Vec<(BagColor, usize)>,
+    ) -> Result<(), BagError> {
+        let held_before = self.descendants(color);
+        let previous = self.rules.set_rule(color, contents);
+
+        if let Err(e) = self.rules.contains(color, color) {
+            match previous {
+                Some(previous) => self.rules.set_rule(color, previous),
+                None => self.rules.remove_rule(color),
+            };
+            return Err(e);
+        }
+
+        self.reindex(color, previous.unwrap_or_default(), held_before);
+        Ok(())
+    }
+
+    pub fn remove_rule(&mut self, color: &str) -> Option<Vec<(BagColor, usize)>> {
+        let held_before = self.descendants(color);
+        let previous = self.rules.remove_rule(color)?;
+
+        self.reindex(color, previous.clone(), held_before);
+        Some(previous)
+    }
+
+    pub fn apply(&mut self, line: &str) -> Result<(), BagError> {
+        let (color, contents) = parse_rule(line).ok_or_else(|| BagError::Parse(line.to_owned()))?;
+        self.set_rule(&color, contents)
+    }
+
+    fn reindex(
+        &mut self,
+        color: &str,
+        previous: Vec<(BagColor, usize)>,
+        held_before: BTreeSet<BagColor>,
+    ) {
+        for (child, _) in previous {
+            if let Some(parents) = self.parents.get_mut(&child) {
+                parents.remove(color);
+            }
+        }
+        for (child, _) in self.rules.children(color) {
+            self.parents
+                .entry(child.clone())
+                .or_default()
+                .insert(color.to_owned());
+        }
+
+        for held in held_before.union(&self.descendants(color)) {
+            self.ancestors.remove(held);
+        }
+
+        self.counts.remove(color);
+        for holder in self.uncached_ancestors(color) {
+            self.counts.remove(&holder);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnswerChange {
+    Ancestors {
+        color: BagColor,
+        before: BTreeSet<BagColor>,
+        after: BTreeSet<BagColor>,
+    },
+    Contained {
+        color: BagColor,
+        before: Result<usize, BagError>,
+        after: Result<usize, BagError>,
+    },
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RuleDiff {
+    pub added: Vec<BagColor>,
+    pub removed: Vec<BagColor>,
+    pub changed: Vec<BagColor>,
+    pub answers: Vec<AnswerChange>,
+}
+
+// Compares the rules themselves and, for every color in either set, the
+// `ancestors` and `count_contained` answers.
+pub fn diff(before: &BagRules, after: &BagRules) -> Result<RuleDiff, BagError> {
+    let mut diff = RuleDiff::default();
+
+    let defined = |rules: &BagRules| rules.rules.keys().cloned().collect::<BTreeSet<_>>();
+    let (old, new) = (defined(before), defined(after));
+    diff.added = new.difference(&old).cloned().collect();
+    diff.removed = old.difference(&new).cloned().collect();
+    diff.changed = old
+        .intersection(&new)
+        .filter(|c| {
+            let sorted = |rules: &BagRules| {
+                let mut rule = rules.rules[*c].clone();
+                rule.sort();
+                rule
+            };
+            sorted(before) != sorted(after)
+        })
+        .cloned()
+        .collect();
+
+    let mut old = IncrementalRules::try_from(before.clone())?;
+    let mut new = IncrementalRules::try_from(after.clone())?;
+    let colors = before.colors_owned();
+
+    for color in colors.union(&after.colors_owned()) {
+        let (a, b) = (old.ancestors(color).clone(), new.ancestors(color).clone());
+        if a != b {
+            diff.answers.push(AnswerChange::Ancestors {
+                color: color.clone(),
+                before: a,
+                after: b,
+            });
+        }
+
+        let (a, b) = (old.count_contained(color), new.count_contained(color));
+        if a != b {
+            diff.answers.push(AnswerChange::Contained {
+                color: color.clone(),
+                before: a,
+                after: b,
+            });
+        }
+    }
+
+    Ok(diff)
+}
+
 mod parser {
     use nom::{
         branch::alt,
@@ -128,23 +819,16 @@ mod parser {
     }
 }
 
-#[aoc_generator(day7, part1, reverse)]
-pub fn generator_part1(input: &str) -> HashMap<String, Vec<String>> {
-    let mut parents = HashMap::new();
+fn parse_rule(line: &str) -> Option<(BagColor, Vec<(BagColor, usize)>)> {
+    let (adj, color, bag_rules) = parser::rule(line).ok()?.1;
 
-    // Build a map from child to parent
-    for line in input.lines() {
-        let (parent_adj, parent_color, bag_rules) = parser::rule(line).unwrap().1;
-
-        for (_, adj, color) in bag_rules {
-            parents
-                .entry(format!("{} {}", adj, color))
-                .or_insert_with(Vec::new)
-                .push(format!("{} {}", parent_adj, parent_color));
-        }
-    }
-
-    parents
+    Some((
+        format!("{} {}", adj, color),
+        bag_rules
+            .into_iter()
+            .map(|(count, adj, color)| (format!("{} {}", adj, color), count))
+            .collect(),
+    ))
 }
 
 #[aoc_generator(day7)]
@@ -152,15 +836,9 @@ pub fn generator(input: &str) -> Option<BagRules> {
     let mut rules = HashMap::new();
 
     for line in input.lines() {
-        let (adj, color, bag_rules) = parser::rule(line).ok()?.1;
-
-        let entry = rules
-            .entry(format!("{} {}", adj, color))
-            .or_insert_with(Vec::new);
+        let (color, contents) = parse_rule(line)?;
 
-        for (count, adj, color) in bag_rules {
-            entry.push((format!("{} {}", adj, color), count))
-        }
+        rules.entry(color).or_insert_with(Vec::new).extend(contents);
     }
 
     Some(BagRules { rules })
@@ -168,33 +846,32 @@ pub fn generator(input: &str) -> Option<BagRules> {
 
 #[aoc(day7, part1)]
 pub fn part1(inputs: &BagRules) -> usize {
-    inputs
-        .rules
-        .iter()
-        .filter(|&(color, _)| inputs.contains(color, "shiny gold"))
-        .count()
+    inputs.count_holders(TARGET).unwrap()
 }
 
-#[aoc(day7, part1, reverse)]
-pub fn part1_two(parent_map: &HashMap<String, Vec<String>>) -> usize {
-    let mut stack = vec!["shiny gold"];
-    let mut ans = std::collections::HashSet::new();
+#[aoc(day7, part2)]
+pub fn part2(inputs: &BagRules) -> usize {
+    inputs.count_contained(TARGET).unwrap()
+}
 
-    while let Some(current) = stack.pop() {
-        if let Some(parents) = parent_map.get(current) {
-            for parent in parents {
-                stack.push(parent);
-                ans.insert(parent);
-            }
-        }
-    }
+#[aoc_generator(day7, part1, graph)]
+pub fn generator_graph_part1(input: &str) -> Option<BagGraph> {
+    BagGraph::try_from(&generator(input)?).ok()
+}
 
-    ans.len()
+#[aoc_generator(day7, part2, graph)]
+pub fn generator_graph_part2(input: &str) -> Option<BagGraph> {
+    generator_graph_part1(input)
 }
 
-#[aoc(day7, part2)]
-pub fn part2(inputs: &BagRules) -> usize {
-    inputs.count_contained("shiny gold")
+#[aoc(day7, part1, graph)]
+pub fn part1_graph(graph: &BagGraph) -> usize {
+    graph.ancestors(TARGET).len()
+}
+
+#[aoc(day7, part2, graph)]
+pub fn part2_graph(graph: &BagGraph) -> usize {
+    graph.total_contained(TARGET).unwrap()
 }
 
 #[cfg(test)]
@@ -259,14 +936,491 @@ dark violet bags contain no other bags.";
     pub fn test2() {
         let bags = generator(SAMPLE1).unwrap();
 
-        assert_eq!(bags.count_contained("faded blue"), 0);
-        assert_eq!(bags.count_contained("vibrant plum"), 11);
-        assert_eq!(bags.count_contained("dark olive"), 7);
+        assert_eq!(bags.count_contained("faded blue"), Ok(0));
+        assert_eq!(bags.count_contained("vibrant plum"), Ok(11));
+        assert_eq!(bags.count_contained("dark olive"), Ok(7));
 
         assert_eq!(part2(&generator(SAMPLE1).unwrap()), 32);
         assert_eq!(part2(&generator(SAMPLE2).unwrap()), 126);
     }
 
+    #[test]
+    pub fn test_contains() {
+        let bags = generator(SAMPLE1).unwrap();
+
+        assert_eq!(bags.contains("light red", "shiny gold"), Ok(true));
+        assert_eq!(bags.contains("shiny gold", "shiny gold"), Ok(false));
+        assert_eq!(bags.contains("faded blue", "shiny gold"), Ok(false));
+        assert_eq!(bags.check_acyclic(), Ok(()));
+    }
+
+    #[test]
+    pub fn test_cycle() {
+        let bags = generator(
+            "light red bags contain 1 dark red bag.
+dark red bags contain 2 pale red bags, 1 faded blue bag.
+pale red bags contain 3 light red bags.
+faded blue bags contain no other bags.
+shiny gold bags contain 1 faded blue bag.",
+        )
+        .unwrap();
+        let cycle = BagError::Cycle(
+            ["dark red", "pale red", "light red", "dark red"]
+                .map(String::from)
+                .to_vec(),
+        );
+
+        assert_eq!(bags.count_contained("dark red"), Err(cycle.clone()));
+        assert_eq!(bags.contains("dark red", "shiny gold"), Err(cycle.clone()));
+        assert_eq!(
+            cycle.to_string(),
+            "bag cycle: dark red -> pale red -> light red -> dark red"
+        );
+        assert!(bags.check_acyclic().is_err());
+        assert!(bags.count_holders("faded blue").is_err());
+        assert_eq!(bags.count_contained("shiny gold"), Ok(1));
+    }
+
+    // Each level holds two bags of each of the next level's two colors, so the
+    // unmemoized recursion would visit 2^levels paths.
+    fn deep_rules(levels: usize) -> BagRules {
+        let color = |level: usize, side: &str| format!("{side} {}", "x".repeat(level + 1));
+        let mut input = (0..levels)
+            .flat_map(|i| {
+                ["light", "dark"].map(|side| {
+                    format!(
+                        "{} bags contain 2 {} bags, 2 {} bags.",
+                        color(i, side),
+                        color(i + 1, "light"),
+                        color(i + 1, "dark")
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        input.push(format!(
+            "{} bags contain no other bags.",
+            color(levels, "light")
+        ));
+        input.push(format!(
+            "{} bags contain no other bags.",
+            color(levels, "dark")
+        ));
+
+        generator(&input.join("\n")).unwrap()
+    }
+
+    #[test]
+    pub fn test_deep() {
+        let bags = deep_rules(20);
+        // 4 + 4^2 + ... + 4^20
+        let expected = (1..=20).map(|i| 4_usize.pow(i)).sum::<usize>();
+
+        assert_eq!(bags.count_contained("light x"), Ok(expected));
+        assert_eq!(
+            bags.contains("light x", &format!("dark {}", "x".repeat(21))),
+            Ok(true)
+        );
+        assert_eq!(
+            bags.count_holders(&format!("dark {}", "x".repeat(21))),
+            Ok(40)
+        );
+
+        let bags = deep_rules(40);
+        assert_eq!(
+            bags.count_contained("light x"),
+            Err(BagError::Overflow(format!("light {}", "x".repeat(9))))
+        );
+        let expected = (1..=40)
+            .map(|i| BigUint::from(4_u32).pow(i))
+            .sum::<BigUint>();
+        assert_eq!(bags.count_contained_big("light x"), Ok(expected));
+    }
+
+    // `len` colors, each holding one bag of the next; `cyclic` closes the loop.
+    fn chain_rules(len: usize, cyclic: bool) -> BagRules {
+        let color = |mut i: usize| {
+            let mut name = String::from("deep ");
+            for _ in 0..4 {
+                name.push(char::from(b'a' + (i % 26) as u8));
+                i /= 26;
+            }
+            name
+        };
+        let mut input = (0..len - 1)
+            .map(|i| format!("{} bags contain 1 {} bag.", color(i), color(i + 1)))
+            .collect::<Vec<_>>();
+        input.push(match cyclic {
+            true => format!("{} bags contain 1 {} bag.", color(len - 1), color(0)),
+            false => format!("{} bags contain no other bags.", color(len - 1)),
+        });
+
+        generator(&input.join("\n")).unwrap()
+    }
+
+    #[test]
+    pub fn test_long_chain() {
+        let bags = chain_rules(20_000, false);
+
+        assert_eq!(bags.check_acyclic(), Ok(()));
+        assert_eq!(bags.count_contained("deep aaaa"), Ok(19_999));
+        assert_eq!(bags.count_contained_big("deep aaaa"), Ok(19_999_u32.into()));
+        assert_eq!(bags.contains("deep aaaa", "deep fpdb"), Ok(true));
+        assert_eq!(bags.count_holders("deep fpdb"), Ok(19_999));
+
+        let bags = chain_rules(20_000, true);
+        match bags.check_acyclic() {
+            Err(BagError::Cycle(colors)) => {
+                assert_eq!(colors.len(), 20_001);
+                assert_eq!(colors.first(), colors.last());
+            }
+            other => panic!("expected a cycle, got {other:?}"),
+        }
+        assert!(matches!(
+            bags.count_contained("deep aaaa"),
+            Err(BagError::Cycle(_))
+        ));
+        match BagGraph::try_from(&bags) {
+            Err(BagError::Cycle(colors)) => {
+                assert_eq!(colors.len(), 20_001);
+                assert_eq!(colors[..2], ["deep aaaa", "deep baaa"]);
+                assert_eq!(colors.last().map(String::as_str), Some("deep aaaa"));
+            }
+            other => panic!("expected a cycle, got {other:?}"),
+        }
+
+        let bags = chain_rules(20_000, false);
+        let graph = BagGraph::try_from(&bags).unwrap();
+        assert_eq!(graph.total_contained("deep aaaa"), Ok(19_999));
+        assert_eq!(graph.ancestors("deep fpdb").len(), 19_999);
+
+        let mut rules = IncrementalRules::try_from(bags).unwrap();
+        assert_eq!(rules.count_contained("deep aaaa"), Ok(19_999));
+        assert!(matches!(
+            rules.apply("deep fpdb bags contain 1 deep aaaa bag."),
+            Err(BagError::Cycle(colors)) if colors.len() == 20_001
+        ));
+        assert_eq!(rules.count_contained("deep aaaa"), Ok(19_999));
+    }
+
+    #[test]
+    pub fn test_graph() {
+        let graph = BagGraph::try_from(&generator(SAMPLE1).unwrap()).unwrap();
+
+        assert_eq!(
+            graph.ancestors("shiny gold"),
+            BTreeSet::from(["bright white", "dark orange", "light red", "muted yellow"])
+        );
+        assert_eq!(
+            graph.descendants("shiny gold"),
+            BTreeSet::from(["dark olive", "dotted black", "faded blue", "vibrant plum"])
+        );
+        assert!(graph.descendants("faded blue").is_empty());
+        assert!(graph.ancestors("no such").is_empty());
+
+        assert_eq!(graph.multiplicity("light red", "shiny gold"), Ok(5));
+        assert_eq!(graph.multiplicity("shiny gold", "faded blue"), Ok(13));
+        assert_eq!(graph.multiplicity("shiny gold", "light red"), Ok(0));
+        assert_eq!(graph.multiplicity("shiny gold", "shiny gold"), Ok(0));
+        assert_eq!(graph.total_contained("shiny gold"), Ok(32));
+
+        assert_eq!(
+            graph.topological_order(),
+            vec![
+                "dark orange",
+                "light red",
+                "bright white",
+                "muted yellow",
+                "shiny gold",
+                "dark olive",
+                "vibrant plum",
+                "dotted black",
+                "faded blue"
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_graph_paths() {
+        let graph = BagGraph::try_from(&generator(SAMPLE1).unwrap()).unwrap();
+
+        assert_eq!(
+            graph.paths("light red", "shiny gold"),
+            Ok(vec![
+                ContainmentPath {
+                    colors: vec!["light red", "bright white", "shiny gold"],
+                    count: 1
+                },
+                ContainmentPath {
+                    colors: vec!["light red", "muted yellow", "shiny gold"],
+                    count: 4
+                },
+            ])
+        );
+
+        let paths = graph.paths("light red", "faded blue").unwrap();
+        assert_eq!(paths.len(), 5);
+        assert_eq!(
+            paths.iter().map(|p| p.count).sum::<usize>(),
+            graph.multiplicity("light red", "faded blue").unwrap()
+        );
+        assert_eq!(graph.paths("faded blue", "light red"), Ok(vec![]));
+    }
+
+    #[test]
+    pub fn test_graph_parts() {
+        assert_eq!(part1_graph(&generator_graph_part1(SAMPLE1).unwrap()), 4);
+        assert_eq!(part2_graph(&generator_graph_part2(SAMPLE1).unwrap()), 32);
+        assert_eq!(part2_graph(&generator_graph_part2(SAMPLE2).unwrap()), 126);
+
+        let cyclic = generator(
+            "light red bags contain 1 dark red bag.\ndark red bags contain 1 light red bag.",
+        )
+        .unwrap();
+        assert_eq!(
+            BagGraph::try_from(&cyclic).unwrap_err(),
+            BagError::Cycle(vec![
+                "dark red".into(),
+                "light red".into(),
+                "dark red".into()
+            ])
+        );
+
+        // Colors that are only below the cycle are not part of it.
+        let cyclic = generator(
+            "shiny gold bags contain 1 light red bag.
+light red bags contain 1 dark red bag.
+dark red bags contain 1 light red bag, 2 faded blue bags.",
+        )
+        .unwrap();
+        assert_eq!(
+            BagGraph::try_from(&cyclic).unwrap_err(),
+            BagError::Cycle(vec![
+                "dark red".into(),
+                "light red".into(),
+                "dark red".into()
+            ])
+        );
+        assert_eq!(
+            BagGraph::try_from(&cyclic).unwrap_err().to_string(),
+            "bag cycle: dark red -> light red -> dark red"
+        );
+
+        // The walk starts below the cycle, at the first stuck color.
+        let cyclic = generator(
+            "dark red bags contain 1 muted yellow bag, 1 bright white bag.
+muted yellow bags contain 1 dark red bag.
+bright white bags contain no other bags.",
+        )
+        .unwrap();
+        assert_eq!(
+            BagGraph::try_from(&cyclic).unwrap_err(),
+            BagError::Cycle(vec![
+                "dark red".into(),
+                "muted yellow".into(),
+                "dark red".into()
+            ])
+        );
+
+        let cyclic = generator("faded blue bags contain 1 faded blue bag.").unwrap();
+        assert_eq!(
+            BagGraph::try_from(&cyclic).unwrap_err(),
+            BagError::Cycle(vec!["faded blue".into(), "faded blue".into()])
+        );
+    }
+
+    fn assert_fresh(rules: &mut IncrementalRules) {
+        let fresh = IncrementalRules::try_from(rules.rules().clone()).unwrap();
+
+        for color in rules.rules().colors_owned() {
+            assert_eq!(
+                rules.count_contained(&color),
+                rules.rules().count_contained(&color),
+                "{color}"
+            );
+            assert_eq!(
+                rules.ancestors(&color),
+                &fresh.uncached_ancestors(&color),
+                "{color}"
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_incremental() {
+        let mut rules = IncrementalRules::try_from(generator(SAMPLE1).unwrap()).unwrap();
+
+        assert_eq!(rules.ancestors("shiny gold").len(), 4);
+        assert_eq!(rules.count_contained("shiny gold"), Ok(32));
+        assert_eq!(rules.count_contained("light red"), Ok(186));
+        assert_eq!(rules.ancestors("faded blue").len(), 7);
+        assert_eq!(rules.ancestors("dotted black").len(), 7);
+
+        // Only the plum subtree and its holders are affected.
+        rules
+            .apply("vibrant plum bags contain 1 faded blue bag.")
+            .unwrap();
+        assert_eq!(rules.is_cached("shiny gold"), (true, false));
+        assert_eq!(rules.is_cached("light red"), (false, false));
+        assert_eq!(rules.is_cached("dark olive"), (false, true));
+        assert_eq!(rules.is_cached("faded blue"), (false, true));
+        assert_eq!(rules.is_cached("dotted black"), (false, true));
+
+        assert_eq!(rules.count_contained("shiny gold"), Ok(1 + 7 + 2 * 2));
+        assert_eq!(rules.count_contained("vibrant plum"), Ok(1));
+        assert_eq!(rules.ancestors("dotted black").len(), 6);
+        assert_fresh(&mut rules);
+
+        // A new bag inside shiny gold.
+        rules
+            .apply("faded blue bags contain 2 posh teal bags.")
+            .unwrap();
+        assert_eq!(rules.count_contained("shiny gold"), Ok(1 + 13 + 2 * 4));
+        assert_eq!(
+            rules.ancestors("posh teal").len(),
+            rules.ancestors("faded blue").len() + 1
+        );
+        assert_fresh(&mut rules);
+
+        let error = rules
+            .apply("posh teal bags contain 1 light red bag.")
+            .unwrap_err();
+        assert!(matches!(error, BagError::Cycle(_)));
+        assert_eq!(rules.rules().rule("posh teal"), None);
+        assert_eq!(rules.count_contained("shiny gold"), Ok(22));
+
+        assert_eq!(
+            rules.remove_rule("faded blue"),
+            Some(vec![("posh teal".to_owned(), 2)])
+        );
+        assert!(rules.ancestors("posh teal").is_empty());
+        assert_eq!(rules.count_contained("shiny gold"), Ok(12));
+        assert_fresh(&mut rules);
+        assert_eq!(rules.remove_rule("faded blue"), None);
+        assert!(matches!(rules.apply("not a rule"), Err(BagError::Parse(_))));
+    }
+
+    #[test]
+    pub fn test_diff() {
+        let before = generator(SAMPLE1).unwrap();
+        let mut after = before.clone();
+
+        assert_eq!(diff(&before, &after), Ok(RuleDiff::default()));
+
+        after.set_rule("dark olive", vec![("faded blue".to_owned(), 3)]);
+        after.set_rule("posh teal", vec![]);
+        after.remove_rule("dotted black");
+        let changes = diff(&before, &after).unwrap();
+
+        assert_eq!(changes.added, vec!["posh teal"]);
+        assert_eq!(changes.removed, vec!["dotted black"]);
+        assert_eq!(changes.changed, vec!["dark olive"]);
+        assert_eq!(
+            changes.answers,
+            vec![
+                AnswerChange::Contained {
+                    color: "bright white".to_owned(),
+                    before: Ok(33),
+                    after: Ok(29),
+                },
+                AnswerChange::Contained {
+                    color: "dark olive".to_owned(),
+                    before: Ok(7),
+                    after: Ok(3),
+                },
+                AnswerChange::Contained {
+                    color: "dark orange".to_owned(),
+                    before: Ok(406),
+                    after: Ok(362),
+                },
+                AnswerChange::Ancestors {
+                    color: "dotted black".to_owned(),
+                    before: [
+                        "bright white",
+                        "dark olive",
+                        "dark orange",
+                        "light red",
+                        "muted yellow",
+                        "shiny gold",
+                        "vibrant plum"
+                    ]
+                    .map(String::from)
+                    .into(),
+                    after: [
+                        "bright white",
+                        "dark orange",
+                        "light red",
+                        "muted yellow",
+                        "shiny gold",
+                        "vibrant plum"
+                    ]
+                    .map(String::from)
+                    .into(),
+                },
+                AnswerChange::Contained {
+                    color: "light red".to_owned(),
+                    before: Ok(186),
+                    after: Ok(166),
+                },
+                AnswerChange::Contained {
+                    color: "muted yellow".to_owned(),
+                    before: Ok(75),
+                    after: Ok(67),
+                },
+                AnswerChange::Contained {
+                    color: "shiny gold".to_owned(),
+                    before: Ok(32),
+                    after: Ok(28),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_dot() {
+        let bags = generator(SAMPLE1).unwrap();
+
+        assert_eq!(
+            bags.to_dot(Scope::ReachableFrom("shiny gold")),
+            r#"digraph bags {
+    "dark olive";
+    "dotted black";
+    "faded blue";
+    "shiny gold";
+    "vibrant plum";
+    "dark olive" -> "dotted black" [label="4"];
+    "dark olive" -> "faded blue" [label="3"];
+    "shiny gold" -> "dark olive" [label="1"];
+    "shiny gold" -> "vibrant plum" [label="2"];
+    "vibrant plum" -> "dotted black" [label="6"];
+    "vibrant plum" -> "faded blue" [label="5"];
+}"#
+        );
+        assert_eq!(
+            bags.to_dot(Scope::Reaching("shiny gold")),
+            r#"digraph bags {
+    "bright white";
+    "dark orange";
+    "light red";
+    "muted yellow";
+    "shiny gold";
+    "bright white" -> "shiny gold" [label="1"];
+    "dark orange" -> "bright white" [label="3"];
+    "dark orange" -> "muted yellow" [label="4"];
+    "light red" -> "bright white" [label="1"];
+    "light red" -> "muted yellow" [label="2"];
+    "muted yellow" -> "shiny gold" [label="2"];
+}"#
+        );
+
+        let all = bags.to_dot(Scope::All);
+        assert_eq!(all.matches(" -> ").count(), 13);
+        assert_eq!(all.lines().count(), 2 + 9 + 13);
+        assert_eq!(
+            bags.to_dot(Scope::ReachableFrom("faded blue")),
+            "digraph bags {\n    \"faded blue\";\n}"
+        );
+    }
+
     mod regression {
         use super::*;
 
@@ -278,6 +1432,14 @@ dark violet bags contain no other bags.";
             let input = INPUT.trim_end_matches('\n'); // Trims trailing newline
             assert_eq!(part1(&generator(input).unwrap()), ANSWERS.0);
             assert_eq!(part2(&generator(input).unwrap()), ANSWERS.1);
+            assert_eq!(
+                part1_graph(&generator_graph_part1(input).unwrap()),
+                ANSWERS.0
+            );
+            assert_eq!(
+                part2_graph(&generator_graph_part2(input).unwrap()),
+                ANSWERS.1
+            );
         }
     }
 }
@@ -0,0 +1,240 @@
+use super::vm::{Flow, Instr, Machine, VmError, Word};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Condition {
+    Equals(Word),
+    NotEquals(Word),
+    Below(Word),
+    Above(Word),
+    // Watches the register: the step changed its value.
+    Changed,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Breakpoint {
+    // Stops before the instruction at this pc runs.
+    Pc(usize),
+    // Stops after a step leaves the register matching the condition.
+    Register {
+        register: usize,
+        condition: Condition,
+    },
+    // Stops before an instruction runs for the second time, like the puzzle.
+    Revisit,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stop {
+    Breakpoint(Breakpoint),
+    Terminated,
+    Halted,
+    // `run` used up its step limit.
+    Limit,
+}
+
+// Undo records, newest last.
+#[derive(Clone, Debug)]
+enum Entry {
+    Step { pc: usize, registers: Vec<Word> },
+    Pc(usize),
+    Register(usize, Word),
+    Instruction(usize, Instr),
+}
+
+#[derive(Clone, Debug)]
+pub struct Debugger<'a> {
+    machine: Machine<'a>,
+    breakpoints: Vec<Breakpoint>,
+    history: Vec<Entry>,
+    visits: Vec<usize>,
+    steps: usize,
+}
+
+impl<'a> Debugger<'a> {
+    pub fn new(machine: Machine<'a>) -> Self {
+        Self {
+            visits: vec![0; machine.program.instructions.len()],
+            machine,
+            breakpoints: Vec::new(),
+            history: Vec::new(),
+            steps: 0,
+        }
+    }
+
+    pub fn machine(&self) -> &Machine<'a> {
+        &self.machine
+    }
+
+    pub fn pc(&self) -> usize {
+        self.machine.pc()
+    }
+
+    pub fn registers(&self) -> &[Word] {
+        &self.machine.registers
+    }
+
+    pub fn register(&self, name: &str) -> Option<Word> {
+        let index = self.machine.set().register(name)?;
+
+        self.machine.registers.get(index).copied()
+    }
+
+    pub fn instruction(&self, index: usize) -> Option<&Instr> {
+        self.machine.program.instructions.get(index)
+    }
+
+    // Times each instruction has run so far.
+    pub fn visits(&self) -> &[usize] {
+        &self.visits
+    }
+
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    pub fn set_pc(&mut self, pc: usize) -> bool {
+        let old = self.machine.pc();
+        if !self.machine.set_pc(pc) {
+            return false;
+        }
+
+        self.history.push(Entry::Pc(old));
+        true
+    }
+
+    pub fn set_register(&mut self, register: usize, value: Word) -> bool {
+        let Some(slot) = self.machine.registers.get_mut(register) else {
+            return false;
+        };
+
+        self.history
+            .push(Entry::Register(register, std::mem::replace(slot, value)));
+        true
+    }
+
+    // The opcode must exist in the machine's instruction set.
+    pub fn set_instruction(&mut self, index: usize, instr: Instr) -> bool {
+        if self.machine.set().spec(instr.op).is_none() {
+            return false;
+        }
+        let Some(slot) = self.machine.program.instructions.get_mut(index) else {
+            return false;
+        };
+
+        self.history
+            .push(Entry::Instruction(index, std::mem::replace(slot, instr)));
+        true
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        if !self.breakpoints.contains(&breakpoint) {
+            self.breakpoints.push(breakpoint);
+        }
+    }
+
+    pub fn remove_breakpoint(&mut self, breakpoint: Breakpoint) -> bool {
+        let len = self.breakpoints.len();
+        self.breakpoints.retain(|&b| b != breakpoint);
+
+        self.breakpoints.len() != len
+    }
+
+    // Runs one instruction, or returns `None` without stepping once the program
+    // has terminated. A failed step leaves the machine untouched.
+    pub fn step(&mut self) -> Result<Option<Flow>, VmError> {
+        let pc = self.machine.pc();
+        if self.machine.is_terminated() {
+            return Ok(None);
+        }
+
+        let registers = self.machine.registers.clone();
+        let flow = self.machine.step()?;
+        self.history.push(Entry::Step { pc, registers });
+        self.visits[pc] += 1;
+        self.steps += 1;
+
+        Ok(Some(flow))
+    }
+
+    fn hit(&self, before: &[Word]) -> Option<Breakpoint> {
+        let pc = self.machine.pc();
+
+        self.breakpoints.iter().copied().find(|&b| match b {
+            Breakpoint::Pc(at) => at == pc,
+            Breakpoint::Revisit => self.visits.get(pc).is_some_and(|&n| n > 0),
+            Breakpoint::Register {
+                register,
+                condition,
+            } => {
+                let Some(&value) = self.machine.registers.get(register) else {
+                    return false;
+                };
+
+                match condition {
+                    Condition::Equals(n) => value == n,
+                    Condition::NotEquals(n) => value != n,
+                    Condition::Below(n) => value < n,
+                    Condition::Above(n) => value > n,
+                    Condition::Changed => before.get(register) != Some(&value),
+                }
+            }
+        })
+    }
+
+    // Steps until a breakpoint is hit, the program ends or `limit` steps have
+    // run. With a limit of zero it only reports whether the program has ended.
+    pub fn run(&mut self, limit: usize) -> Result<Stop, VmError> {
+        for _ in 0..limit {
+            let before = self.machine.registers.clone();
+            match self.step()? {
+                None => return Ok(Stop::Terminated),
+                Some(Flow::Halt) => return Ok(Stop::Halted),
+                Some(_) => {}
+            }
+            if let Some(breakpoint) = self.hit(&before) {
+                return Ok(Stop::Breakpoint(breakpoint));
+            }
+        }
+
+        Ok(if self.machine.is_terminated() {
+            Stop::Terminated
+        } else {
+            Stop::Limit
+        })
+    }
+
+    // Undoes the last `steps` steps along with any edits made after them, and
+    // returns how many steps were actually undone.
+    pub fn rewind(&mut self, steps: usize) -> usize {
+        let mut undone = 0;
+
+        while undone < steps {
+            let Some(entry) = self.history.pop() else {
+                break;
+            };
+
+            match entry {
+                Entry::Step { pc, registers } => {
+                    self.machine.set_pc(pc);
+                    self.machine.registers = registers;
+                    self.visits[pc] -= 1;
+                    self.steps -= 1;
+                    undone += 1;
+                }
+                Entry::Pc(pc) => {
+                    self.machine.set_pc(pc);
+                }
+                Entry::Register(register, value) => self.machine.registers[register] = value,
+                Entry::Instruction(index, instr) => {
+                    self.machine.program.instructions[index] = instr
+                }
+            }
+        }
+
+        undone
+    }
+}
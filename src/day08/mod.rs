@@ -0,0 +1,1212 @@
+use std::convert::TryFrom;
+use std::{collections::VecDeque, fmt};
+
+pub mod debugger;
+pub mod vm;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct SimpleComputer {
+    instructions: Vec<Instruction>,
+}
+
+impl SimpleComputer {
+    pub fn execute(&self) -> Outcome {
+        self.run(None)
+    }
+
+    fn run(&self, mut trace: Option<&mut Vec<TraceStep>>) -> Outcome {
+        let len = self.instructions.len();
+        let mut pc = 0;
+        // Position of each instruction in `order`, once it has run.
+        let mut seen = vec![None; len];
+        let mut order = Vec::new();
+        let mut accumulator: vm::Word = 0;
+
+        while pc != len {
+            if let Some(start) = seen[pc] {
+                return Outcome::InfiniteLoop {
+                    accumulator,
+                    cycle: order.split_off(start),
+                };
+            }
+            seen[pc] = Some(order.len());
+            order.push(pc);
+
+            let instruction = self.instructions[pc];
+            let (next, after) = match instruction {
+                Instruction::Acc(n) => match accumulator.checked_add(n.into()) {
+                    Some(after) => (pc + 1, after),
+                    None => return Outcome::Overflow { accumulator, pc },
+                },
+                Instruction::Jmp(n) => {
+                    let target = pc as i64 + i64::from(n);
+                    match usize::try_from(target) {
+                        Ok(target) if target <= len => (target, accumulator),
+                        _ => {
+                            return Outcome::OutOfBounds {
+                                accumulator,
+                                pc,
+                                target,
+                            };
+                        }
+                    }
+                }
+                Instruction::Nop(_) => (pc + 1, accumulator),
+            };
+
+            if let Some(trace) = trace.as_mut() {
+                trace.push(TraceStep {
+                    pc,
+                    instruction,
+                    before: accumulator,
+                    after,
+                });
+            }
+            (pc, accumulator) = (next, after);
+        }
+
+        Outcome::Terminated { accumulator }
+    }
+
+    pub fn trace(&self) -> Trace {
+        let mut steps = Vec::new();
+        let outcome = self.run(Some(&mut steps));
+
+        Trace { steps, outcome }
+    }
+
+    pub fn coverage(&self) -> Coverage {
+        let trace = self.trace();
+        let mut counts = vec![0; self.instructions.len()];
+        for step in &trace.steps {
+            counts[step.pc] += 1;
+        }
+        // The instruction that failed was reached, even though it did not run.
+        if let Outcome::OutOfBounds { pc, .. } | Outcome::Overflow { pc, .. } = trace.outcome {
+            counts[pc] += 1;
+        }
+
+        Coverage {
+            instructions: self.instructions.clone(),
+            counts,
+            outcome: trace.outcome,
+        }
+    }
+
+    fn swap(&mut self, n: usize) -> bool {
+        self.instructions[n].swap()
+    }
+
+    // Where the instruction at `index` continues, with `jmp` and `nop` exchanged
+    // if `swapped`. `len` stands for termination; `None` leaves the program.
+    fn successor(&self, index: usize, swapped: bool) -> Option<usize> {
+        let mut instruction = self.instructions[index];
+        if swapped {
+            instruction.swap();
+        }
+
+        let offset = match instruction {
+            Instruction::Jmp(n) => n,
+            _ => 1,
+        };
+
+        index
+            .checked_add_signed(offset.try_into().ok()?)
+            .filter(|&target| target <= self.instructions.len())
+    }
+
+    fn increment(&self, index: usize) -> i64 {
+        match self.instructions[index] {
+            Instruction::Acc(n) => n.into(),
+            _ => 0,
+        }
+    }
+
+    // Every instruction has exactly one successor, so the instructions that
+    // reach termination form a tree rooted at the end of the program. Walking
+    // that tree once and the unpatched run once finds every repairing swap.
+    pub fn analyze_repairs(&self) -> RepairAnalysis {
+        let len = self.instructions.len();
+        let mut predecessors = vec![Vec::new(); len + 1];
+        for index in 0..len {
+            if let Some(target) = self.successor(index, false) {
+                predecessors[target].push(index);
+            }
+        }
+
+        // The accumulator gained between an instruction and termination.
+        let mut to_end = vec![None; len + 1];
+        to_end[len] = Some(0);
+        let mut queue = VecDeque::from([len]);
+        while let Some(target) = queue.pop_front() {
+            for &index in &predecessors[target] {
+                to_end[index] = to_end[target].map(|acc| acc + self.increment(index));
+                queue.push_back(index);
+            }
+        }
+
+        let mut path = Vec::new();
+        let mut before = Vec::new();
+        let mut seen = vec![false; len];
+        let (mut pc, mut accumulator) = (Some(0), 0);
+        while let Some(index) = pc.filter(|&index| index < len && !seen[index]) {
+            seen[index] = true;
+            path.push(index);
+            before.push(accumulator);
+            accumulator += self.increment(index);
+            pc = self.successor(index, false);
+        }
+
+        let terminating = to_end[..len].iter().map(Option::is_some).collect();
+        // With the unpatched program stuck, no instruction on its path reaches
+        // the end, so the patched run cannot come back to the swap.
+        let repairs = if to_end[0].is_some() {
+            Vec::new()
+        } else {
+            path.iter()
+                .zip(before)
+                .filter_map(|(&index, before)| {
+                    let target = self
+                        .successor(index, true)
+                        .filter(|_| !matches!(self.instructions[index], Instruction::Acc(_)))?;
+
+                    Some(Repair {
+                        index,
+                        accumulator: before + to_end[target]?,
+                    })
+                })
+                .collect()
+        };
+
+        RepairAnalysis {
+            terminating,
+            path,
+            repairs,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Repair {
+    pub index: usize,
+    pub accumulator: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepairAnalysis {
+    // Instructions from which the unpatched program terminates.
+    pub terminating: Vec<bool>,
+    // Instructions the unpatched program runs, in order, until it ends or loops.
+    pub path: Vec<usize>,
+    // Every single swap that makes the program terminate. Empty if it already
+    // terminates or no swap helps.
+    pub repairs: Vec<Repair>,
+}
+
+impl RepairAnalysis {
+    pub fn terminates(&self) -> bool {
+        self.terminating.first().copied().unwrap_or(true)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Terminated {
+        accumulator: vm::Word,
+    },
+    // `cycle` lists the pcs of the loop, starting with the one run twice.
+    InfiniteLoop {
+        accumulator: vm::Word,
+        cycle: Vec<usize>,
+    },
+    // The jump at `pc` would go to `target`, which is neither an instruction
+    // nor the end of the program.
+    OutOfBounds {
+        accumulator: vm::Word,
+        pc: usize,
+        target: i64,
+    },
+    // The `acc` at `pc` would overflow `accumulator`.
+    Overflow {
+        accumulator: vm::Word,
+        pc: usize,
+    },
+}
+
+impl Outcome {
+    // The accumulator when execution stopped.
+    pub fn accumulator(&self) -> vm::Word {
+        match *self {
+            Outcome::Terminated { accumulator }
+            | Outcome::InfiniteLoop { accumulator, .. }
+            | Outcome::OutOfBounds { accumulator, .. }
+            | Outcome::Overflow { accumulator, .. } => accumulator,
+        }
+    }
+
+    pub fn is_terminated(&self) -> bool {
+        matches!(self, Outcome::Terminated { .. })
+    }
+
+    fn to_json(&self) -> String {
+        match self {
+            Outcome::Terminated { accumulator } => {
+                format!(r#"{{"kind":"terminated","accumulator":{accumulator}}}"#)
+            }
+            Outcome::InfiniteLoop { accumulator, cycle } => format!(
+                r#"{{"kind":"infinite_loop","accumulator":{accumulator},"cycle":[{}]}}"#,
+                join(cycle, ",")
+            ),
+            Outcome::OutOfBounds {
+                accumulator,
+                pc,
+                target,
+            } => format!(
+                r#"{{"kind":"out_of_bounds","accumulator":{accumulator},"pc":{pc},"target":{target}}}"#
+            ),
+            Outcome::Overflow { accumulator, pc } => {
+                format!(r#"{{"kind":"overflow","accumulator":{accumulator},"pc":{pc}}}"#)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Terminated { accumulator } => {
+                write!(f, "terminated with accumulator {accumulator}")
+            }
+            Outcome::InfiniteLoop { accumulator, cycle } => write!(
+                f,
+                "infinite loop through {} with accumulator {accumulator}",
+                join(cycle, " -> ")
+            ),
+            Outcome::OutOfBounds {
+                accumulator,
+                pc,
+                target,
+            } => write!(
+                f,
+                "jump from {pc} to {target} out of bounds with accumulator {accumulator}"
+            ),
+            Outcome::Overflow { accumulator, pc } => {
+                write!(f, "overflow at {pc} with accumulator {accumulator}")
+            }
+        }
+    }
+}
+
+fn join<T: ToString>(items: &[T], separator: &str) -> String {
+    items
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceStep {
+    pub pc: usize,
+    pub instruction: Instruction,
+    pub before: vm::Word,
+    pub after: vm::Word,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trace {
+    pub steps: Vec<TraceStep>,
+    pub outcome: Outcome,
+}
+
+impl Trace {
+    // One line per step as `pc: instruction before -> after`.
+    pub fn to_text(&self) -> String {
+        self.steps
+            .iter()
+            .map(|step| {
+                format!(
+                    "{:>4}: {:<10} {} -> {}",
+                    step.pc,
+                    step.instruction.to_string(),
+                    step.before,
+                    step.after
+                )
+            })
+            .chain(std::iter::once(self.outcome.to_string()))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn to_json(&self) -> String {
+        let steps = self
+            .steps
+            .iter()
+            .map(|step| {
+                format!(
+                    r#"{{"pc":{},"instruction":"{}","before":{},"after":{}}}"#,
+                    step.pc, step.instruction, step.before, step.after
+                )
+            })
+            .collect::<Vec<_>>();
+
+        format!(
+            r#"{{"steps":[{}],"outcome":{}}}"#,
+            steps.join(","),
+            self.outcome.to_json()
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coverage {
+    pub instructions: Vec<Instruction>,
+    // Whether each instruction was reached: a run stops before repeating one,
+    // so every count is 0 or 1.
+    pub counts: Vec<usize>,
+    pub outcome: Outcome,
+}
+
+impl Coverage {
+    pub fn dead_code(&self) -> Vec<usize> {
+        (0..self.counts.len())
+            .filter(|&pc| self.counts[pc] == 0)
+            .collect()
+    }
+
+    // The first instruction that would have run twice.
+    pub fn loop_start(&self) -> Option<usize> {
+        match &self.outcome {
+            Outcome::InfiniteLoop { cycle, .. } => cycle.first().copied(),
+            _ => None,
+        }
+    }
+
+    // One line per instruction with its run count, marking dead code and the
+    // start of the loop.
+    pub fn to_text(&self) -> String {
+        let loop_start = self.loop_start();
+
+        self.instructions
+            .iter()
+            .zip(self.counts.iter())
+            .enumerate()
+            .map(|(pc, (instruction, &count))| {
+                let note = match count {
+                    0 => "  dead",
+                    _ if loop_start == Some(pc) => "  loop",
+                    _ => "",
+                };
+
+                format!("{pc:>4}: {:<10} {count}{note}", instruction.to_string())
+            })
+            .chain(std::iter::once(self.outcome.to_string()))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn to_json(&self) -> String {
+        let instructions = self
+            .instructions
+            .iter()
+            .map(|instruction| format!(r#""{instruction}""#))
+            .collect::<Vec<_>>();
+        let loop_start = self
+            .loop_start()
+            .map_or_else(|| "null".to_owned(), |pc| pc.to_string());
+
+        format!(
+            r#"{{"instructions":[{}],"counts":[{}],"dead":[{}],"loop_start":{loop_start},"outcome":{}}}"#,
+            instructions.join(","),
+            join(&self.counts, ","),
+            join(&self.dead_code(), ","),
+            self.outcome.to_json()
+        )
+    }
+
+    // Puts a patched run next to this one, one line per instruction as
+    // `pc: original count  patched count`, marking the swapped instructions
+    // and the ones only one run reached. The programs must be the same length.
+    pub fn compare_text(&self, patched: &Coverage) -> Option<String> {
+        if patched.instructions.len() != self.instructions.len() {
+            return None;
+        }
+
+        let lines = (0..self.instructions.len()).map(|pc| {
+            let (before, after) = (self.instructions[pc], patched.instructions[pc]);
+            let (old, new) = (self.counts[pc], patched.counts[pc]);
+            let note = match (old, new) {
+                _ if before != after => "  patched",
+                (0, 0) => "  dead",
+                (0, _) => "  gained",
+                (_, 0) => "  lost",
+                _ => "",
+            };
+
+            format!(
+                "{pc:>4}: {:<10} {old}  {:<10} {new}{note}",
+                before.to_string(),
+                after.to_string()
+            )
+        });
+
+        Some(
+            lines
+                .chain([
+                    format!("original: {}", self.outcome),
+                    format!("patched: {}", patched.outcome),
+                ])
+                .collect::<Vec<_>>()
+                .join("\n"),
+        )
+    }
+
+    pub fn compare_json(&self, patched: &Coverage) -> Option<String> {
+        if patched.instructions.len() != self.instructions.len() {
+            return None;
+        }
+
+        let changed = (0..self.instructions.len())
+            .filter(|&pc| self.instructions[pc] != patched.instructions[pc])
+            .collect::<Vec<_>>();
+
+        Some(format!(
+            r#"{{"changed":[{}],"original":{},"patched":{}}}"#,
+            join(&changed, ","),
+            self.to_json(),
+            patched.to_json()
+        ))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Instruction {
+    Acc(i32),
+    Jmp(i32),
+    Nop(i32),
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::Acc(n) => write!(f, "acc {n:+}"),
+            Instruction::Jmp(n) => write!(f, "jmp {n:+}"),
+            Instruction::Nop(n) => write!(f, "nop {n:+}"),
+        }
+    }
+}
+
+impl Instruction {
+    fn swap(&mut self) -> bool {
+        *self = match self {
+            Instruction::Jmp(n) => Instruction::Nop(*n),
+            Instruction::Nop(n) => Instruction::Jmp(*n),
+            _ => return false,
+        };
+
+        true
+    }
+}
+
+impl TryFrom<&vm::Program> for SimpleComputer {
+    type Error = vm::AsmError;
+
+    // Expects a program assembled with `vm::InstructionSet::handheld`.
+    fn try_from(program: &vm::Program) -> Result<Self, Self::Error> {
+        let instructions = program
+            .instructions
+            .iter()
+            .zip(program.lines.iter())
+            .map(|(instr, &line)| {
+                let error = |kind| vm::AsmError { line, kind };
+                let arg = instr.args[0];
+                let num = i32::try_from(arg)
+                    .map_err(|_| error(vm::AsmErrorKind::MalformedOperand(format!("{arg:+}"))))?;
+
+                Ok(match instr.op {
+                    vm::ACC => Instruction::Acc(num),
+                    vm::JMP => Instruction::Jmp(num),
+                    vm::NOP => Instruction::Nop(num),
+                    op => return Err(error(vm::AsmErrorKind::UnknownOpcode(format!("#{op}")))),
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(SimpleComputer { instructions })
+    }
+}
+
+impl From<&SimpleComputer> for vm::Program {
+    // Uses the opcodes of `vm::InstructionSet::handheld`.
+    fn from(computer: &SimpleComputer) -> Self {
+        let instructions = computer
+            .instructions
+            .iter()
+            .map(|instruction| match *instruction {
+                Instruction::Acc(n) => (vm::ACC, n),
+                Instruction::Jmp(n) => (vm::JMP, n),
+                Instruction::Nop(n) => (vm::NOP, n),
+            })
+            .map(|(op, n)| vm::Instr {
+                op,
+                args: vec![n.into()],
+            })
+            .collect();
+
+        vm::Program {
+            instructions,
+            lines: (1..=computer.instructions.len()).collect(),
+        }
+    }
+}
+
+#[aoc_generator(day8)]
+pub fn generator(input: &str) -> Result<SimpleComputer, vm::AsmError> {
+    let program = vm::assemble(input, &vm::InstructionSet::handheld())?;
+
+    SimpleComputer::try_from(&program)
+}
+
+#[aoc_generator(day8, part1, vm)]
+pub fn generator_vm(input: &str) -> Result<vm::Program, vm::AsmError> {
+    vm::assemble(input, &vm::InstructionSet::handheld())
+}
+
+#[aoc(day8, part1)]
+pub fn part1(computer: &SimpleComputer) -> vm::Word {
+    computer.execute().accumulator()
+}
+
+#[aoc(day8, part1, vm)]
+pub fn part1_vm(program: &vm::Program) -> i64 {
+    let set = vm::InstructionSet::handheld();
+    let mut machine = vm::Machine::new(&set, program.clone());
+    machine.run().unwrap();
+
+    machine.registers[vm::ACC]
+}
+
+#[aoc(day8, part2)]
+pub fn part2(computer: &SimpleComputer) -> Option<vm::Word> {
+    let len = computer.instructions.len();
+    let mut patched_computer = computer.clone();
+
+    for i in 0..len {
+        if patched_computer.swap(i) {
+            if let Outcome::Terminated { accumulator } = patched_computer.execute() {
+                return Some(accumulator);
+            }
+
+            patched_computer.swap(i);
+        }
+    }
+
+    None
+}
+
+#[aoc(day8, part2, cfg)]
+pub fn part2_cfg(computer: &SimpleComputer) -> Option<vm::Word> {
+    let repair = computer.analyze_repairs().repairs.first().copied()?;
+
+    Some(repair.accumulator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r"nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6";
+
+    #[test]
+    pub fn test_input() {
+        use Instruction::*;
+        // println!("{:?}", generator(SAMPLE));
+
+        assert_eq!(
+            generator(SAMPLE).unwrap(),
+            SimpleComputer {
+                instructions: vec![
+                    Nop(0),
+                    Acc(1),
+                    Jmp(4),
+                    Acc(3),
+                    Jmp(-3),
+                    Acc(-99),
+                    Acc(1),
+                    Jmp(-4),
+                    Acc(6)
+                ]
+            }
+        );
+    }
+
+    #[test]
+    pub fn test1() {
+        assert_eq!(part1(&generator(SAMPLE).unwrap()), 5);
+    }
+
+    #[test]
+    pub fn test2() {
+        assert!(!generator(SAMPLE).unwrap().execute().is_terminated());
+
+        assert_eq!(part2(&generator(SAMPLE).unwrap()), Some(8));
+        assert_eq!(part2_cfg(&generator(SAMPLE).unwrap()), Some(8));
+    }
+
+    #[test]
+    pub fn test_assemble() {
+        let set = vm::InstructionSet::handheld();
+        let labelled = r"# the sample, with labels
+        nop +0
+start:  acc +1        ; comment
+        jmp skip
+back:   acc +3
+        jmp start
+        acc -99
+skip:   acc +1
+        jmp back
+        acc +6
+end:";
+
+        let program = vm::assemble(labelled, &set).unwrap();
+        assert_eq!(
+            program.instructions,
+            vm::assemble(SAMPLE, &set).unwrap().instructions
+        );
+        assert_eq!(program.lines, vec![2, 3, 4, 5, 6, 7, 8, 9, 10]);
+        assert_eq!(SimpleComputer::try_from(&program), generator(SAMPLE));
+        assert_eq!(program.disassemble(&set), SAMPLE);
+        assert_eq!(
+            program.disassemble_labelled(&set),
+            "L0:\n    nop L0\nL1:\n    acc +1\n    jmp L6\nL3:\n    acc +3\n    jmp L1\n    acc -99\nL6:\n    acc +1\n    jmp L3\n    acc +6"
+        );
+
+        let round_trip = vm::assemble(&program.disassemble_labelled(&set), &set).unwrap();
+        assert_eq!(round_trip.instructions, program.instructions);
+    }
+
+    #[test]
+    pub fn test_assemble_errors() {
+        use vm::AsmErrorKind::*;
+
+        let set = vm::InstructionSet::handheld();
+        let error = |source: &str| vm::assemble(source, &set).unwrap_err();
+
+        assert_eq!(error("nop +0\nmul +2").kind, UnknownOpcode("mul".into()));
+        assert_eq!(error("nop +0\nmul +2").line, 2);
+        assert_eq!(error("acc 1x").kind, MalformedOperand("1x".into()));
+        assert_eq!(error("acc x").kind, MalformedOperand("x".into()));
+        assert_eq!(error("acc +-1").kind, MalformedOperand("+-1".into()));
+        assert_eq!(error("jmp nowhere").kind, UnknownLabel("nowhere".into()));
+        assert_eq!(
+            error("a: nop +0\na: nop +0").kind,
+            DuplicateLabel("a".into())
+        );
+        assert_eq!(error("1a: nop +0").kind, MalformedLabel("1a".into()));
+        assert_eq!(
+            error("acc +1 +2").kind,
+            OperandCount {
+                op: "acc".into(),
+                expected: 1,
+                found: 2
+            }
+        );
+        assert_eq!(
+            error("acc").to_string(),
+            "line 1: 'acc' takes 1 operand(s), found 0"
+        );
+        assert_eq!(
+            generator("acc +1\njmp +9999999999").unwrap_err(),
+            vm::AsmError {
+                line: 2,
+                kind: MalformedOperand("+9999999999".into())
+            }
+        );
+        assert!(generator("acc +1\nhlt +0").is_err());
+    }
+
+    #[test]
+    pub fn test_machine() {
+        use vm::OperandKind::*;
+
+        let set = vm::InstructionSet::handheld();
+        let mut machine = vm::Machine::new(&set, vm::assemble(SAMPLE, &set).unwrap());
+        assert_eq!(machine.run(), Ok(vm::Exit::Looped));
+        assert_eq!((machine.pc(), machine.registers[vm::ACC]), (1, 5));
+
+        let patched = SAMPLE.replace("jmp -4", "nop -4");
+        let mut machine = vm::Machine::new(&set, vm::assemble(&patched, &set).unwrap());
+        assert_eq!(machine.run(), Ok(vm::Exit::Terminated));
+        assert_eq!(machine.registers[vm::ACC], 8);
+        assert!(machine.is_terminated());
+
+        let mut machine = vm::Machine::new(&set, vm::assemble("nop +0\njmp -2", &set).unwrap());
+        assert_eq!(
+            machine.run(),
+            Err(vm::VmError::JumpOutOfBounds { pc: 1, target: -1 })
+        );
+
+        let source = format!("acc +{}\nacc +1", i64::MAX);
+        let mut machine = vm::Machine::new(&set, vm::assemble(&source, &set).unwrap());
+        assert_eq!(machine.run(), Err(vm::VmError::Overflow { pc: 1 }));
+
+        // A second register and opcodes that work on any register.
+        let mut set = vm::InstructionSet::handheld();
+        let b = set.add_register("b");
+        assert_eq!(set.add_register("b"), b);
+        set.define("inc", &[Register, Immediate], |regs, args| {
+            regs[args[0] as usize] += args[1];
+            Ok(vm::Flow::Next)
+        });
+        set.define("mov", &[Register, Register], |regs, args| {
+            regs[args[0] as usize] = regs[args[1] as usize];
+            Ok(vm::Flow::Next)
+        });
+        set.define("jnz", &[Register, Offset], |regs, args| {
+            Ok(if regs[args[0] as usize] != 0 {
+                vm::Flow::Jump(args[1])
+            } else {
+                vm::Flow::Next
+            })
+        });
+        set.define("hlt", &[], |_, _| Ok(vm::Flow::Halt));
+
+        let source = r"
+    inc b, +3
+loop:
+    inc acc, +10
+    inc b, -1
+    jnz b, loop
+    mov b, acc
+    hlt
+    acc +1";
+        let program = vm::assemble(source, &set).unwrap();
+        assert_eq!(
+            program.disassemble(&set),
+            "inc b +3\ninc acc +10\ninc b -1\njnz b -2\nmov b acc\nhlt\nacc +1"
+        );
+        assert_eq!(
+            vm::assemble("inc c, +1", &set).unwrap_err().kind,
+            vm::AsmErrorKind::UnknownRegister("c".into())
+        );
+
+        // `run` stops at the first repeated instruction, as in the puzzle.
+        let mut machine = vm::Machine::new(&set, program.clone());
+        assert_eq!(machine.run(), Ok(vm::Exit::Looped));
+
+        let mut machine = vm::Machine::new(&set, program);
+        assert_eq!(machine.run_steps(5), Ok(None));
+        assert_eq!(machine.run_steps(100), Ok(Some(vm::Exit::Halted)));
+        assert_eq!(machine.registers, vec![30, 30]);
+        assert_eq!(machine.pc(), 5);
+
+        // Errors from an opcode are reported at its pc, and leave the machine as
+        // it was before the failed step.
+        set.define("bad", &[Register], |regs, args| {
+            regs[args[0] as usize] = 99;
+            Err(vm::VmError::UnknownOpcode { pc: 0, op: 7 })
+        });
+        set.define("far", &[], |regs, _| {
+            regs[vm::ACC] = 99;
+            Err(vm::VmError::JumpOutOfBounds { pc: 0, target: 50 })
+        });
+        let program = vm::assemble("inc b, +1\nbad b\nfar", &set).unwrap();
+        let mut machine = vm::Machine::new(&set, program);
+        assert_eq!(
+            machine.run(),
+            Err(vm::VmError::UnknownOpcode { pc: 1, op: 7 })
+        );
+        assert_eq!((machine.pc(), &machine.registers[..]), (1, &[0, 1][..]));
+        assert!(machine.set_pc(2));
+        assert_eq!(
+            machine.step(),
+            Err(vm::VmError::JumpOutOfBounds { pc: 2, target: 50 })
+        );
+        assert_eq!((machine.pc(), &machine.registers[..]), (2, &[0, 1][..]));
+
+        let source = format!("inc b, +1\nacc +{}\nacc +1", i64::MAX);
+        let mut machine = vm::Machine::new(&set, vm::assemble(&source, &set).unwrap());
+        assert_eq!(machine.run_steps(10), Err(vm::VmError::Overflow { pc: 2 }));
+        assert_eq!(
+            (machine.pc(), &machine.registers[..]),
+            (2, &[i64::MAX, 1][..])
+        );
+
+        // The pc can only point at an instruction or just past the last one, and
+        // a program shrunk beneath it halts the machine.
+        assert!(!machine.set_pc(4));
+        assert!(machine.set_pc(3));
+        assert!(machine.is_terminated());
+        assert_eq!(machine.run(), Ok(vm::Exit::Terminated));
+        machine.program.instructions.clear();
+        assert_eq!(machine.run(), Ok(vm::Exit::Halted));
+    }
+
+    #[test]
+    pub fn test_debugger() {
+        use debugger::{Breakpoint, Condition, Debugger, Stop};
+
+        let set = vm::InstructionSet::handheld();
+        let computer = generator(SAMPLE).unwrap();
+        let program = vm::Program::from(&computer);
+        assert_eq!(program, vm::assemble(SAMPLE, &set).unwrap());
+
+        let mut debugger = Debugger::new(vm::Machine::new(&set, program));
+        assert_eq!(debugger.run(0), Ok(Stop::Limit));
+        assert_eq!(debugger.steps(), 0);
+        assert_eq!(debugger.step(), Ok(Some(vm::Flow::Next)));
+        assert_eq!(debugger.step(), Ok(Some(vm::Flow::Next)));
+        assert_eq!((debugger.pc(), debugger.register("acc")), (2, Some(1)));
+
+        debugger.add_breakpoint(Breakpoint::Pc(3));
+        assert_eq!(debugger.run(100), Ok(Stop::Breakpoint(Breakpoint::Pc(3))));
+        assert_eq!((debugger.steps(), debugger.registers()), (5, &[2][..]));
+
+        debugger.add_breakpoint(Breakpoint::Register {
+            register: vm::ACC,
+            condition: Condition::Above(4),
+        });
+        assert_eq!(
+            debugger.run(100),
+            Ok(Stop::Breakpoint(Breakpoint::Register {
+                register: vm::ACC,
+                condition: Condition::Above(4)
+            }))
+        );
+        assert_eq!((debugger.pc(), debugger.registers()), (4, &[5][..]));
+
+        debugger.breakpoints().to_vec().into_iter().for_each(|b| {
+            assert!(debugger.remove_breakpoint(b));
+        });
+        debugger.add_breakpoint(Breakpoint::Revisit);
+        assert_eq!(debugger.run(100), Ok(Stop::Breakpoint(Breakpoint::Revisit)));
+        assert_eq!((debugger.pc(), debugger.registers()), (1, &[5][..]));
+        assert_eq!(debugger.visits(), &[1, 1, 1, 1, 1, 0, 1, 1, 0]);
+
+        // Patch the corrupted jump, then check that rewinding undoes it too.
+        assert!(debugger.set_instruction(
+            7,
+            vm::Instr {
+                op: vm::NOP,
+                args: vec![-4]
+            }
+        ));
+        assert!(debugger.set_register(vm::ACC, 100));
+        assert!(!debugger.set_register(1, 0));
+        assert!(!debugger.set_pc(10));
+        assert_eq!(debugger.rewind(1), 1);
+        assert_eq!((debugger.pc(), debugger.registers()), (4, &[5][..]));
+        assert_eq!(debugger.instruction(7).unwrap().op, vm::JMP);
+
+        assert!(debugger.set_instruction(
+            7,
+            vm::Instr {
+                op: vm::NOP,
+                args: vec![-4]
+            }
+        ));
+        assert_eq!(debugger.rewind(100), 6);
+        assert_eq!(
+            (debugger.pc(), debugger.registers(), debugger.steps()),
+            (0, &[0][..], 0)
+        );
+        assert!(debugger.visits().iter().all(|&n| n == 0));
+
+        assert!(debugger.set_instruction(
+            7,
+            vm::Instr {
+                op: vm::NOP,
+                args: vec![-4]
+            }
+        ));
+        debugger.add_breakpoint(Breakpoint::Register {
+            register: vm::ACC,
+            condition: Condition::Changed,
+        });
+        let mut changes = Vec::new();
+        while let Ok(Stop::Breakpoint(_)) = debugger.run(100) {
+            changes.push(debugger.registers()[vm::ACC]);
+        }
+        assert_eq!(changes, vec![1, 2, 8]);
+        assert!(debugger.machine().is_terminated());
+        assert_eq!(debugger.run(100), Ok(Stop::Terminated));
+        assert_eq!(debugger.run(0), Ok(Stop::Terminated));
+        let steps = debugger.steps();
+        assert_eq!(debugger.step(), Ok(None));
+        assert_eq!(debugger.steps(), steps);
+
+        let mut debugger = Debugger::new(vm::Machine::new(
+            &set,
+            vm::assemble("acc +1\njmp -2", &set).unwrap(),
+        ));
+        assert_eq!(debugger.run(1), Ok(Stop::Limit));
+        assert_eq!(
+            debugger.run(1),
+            Err(vm::VmError::JumpOutOfBounds { pc: 1, target: -1 })
+        );
+        assert_eq!((debugger.pc(), debugger.steps()), (1, 1));
+    }
+
+    #[test]
+    pub fn test_repairs() {
+        let analysis = generator(SAMPLE).unwrap().analyze_repairs();
+
+        assert_eq!(analysis.path, vec![0, 1, 2, 6, 7, 3, 4]);
+        assert_eq!(
+            analysis.terminating,
+            vec![false, false, false, false, false, false, false, false, true]
+        );
+        assert_eq!(
+            analysis.repairs,
+            vec![Repair {
+                index: 7,
+                accumulator: 8
+            }]
+        );
+        assert!(!analysis.terminates());
+
+        let fixed = generator(&SAMPLE.replace("jmp -4", "nop -4")).unwrap();
+        assert!(fixed.analyze_repairs().terminates());
+        assert!(fixed.analyze_repairs().repairs.is_empty());
+
+        let stuck = generator("jmp +0\njmp +0").unwrap();
+        assert!(stuck.analyze_repairs().repairs.is_empty());
+        assert_eq!(part2(&stuck), None);
+        assert_eq!(part2_cfg(&stuck), None);
+
+        let analysis = generator("nop +2\njmp +0\nacc +1\njmp -9")
+            .unwrap()
+            .analyze_repairs();
+        assert_eq!(analysis.terminating, vec![false; 4]);
+        assert!(analysis.repairs.is_empty());
+
+        let analysis = generator("nop +3\njmp +0\nacc +1\nacc +2")
+            .unwrap()
+            .analyze_repairs();
+        assert_eq!(
+            analysis.repairs,
+            vec![
+                Repair {
+                    index: 0,
+                    accumulator: 2
+                },
+                Repair {
+                    index: 1,
+                    accumulator: 3
+                }
+            ]
+        );
+        assert!(generator("").unwrap().analyze_repairs().terminates());
+    }
+
+    #[test]
+    pub fn test_repairs_brute_force() {
+        for source in [
+            SAMPLE,
+            "nop +3\njmp +0\nacc +1\nacc +2",
+            "acc +1\nnop +2\njmp -2\njmp -1\nacc +5",
+            "jmp +2\nacc +3\nnop -1\njmp -3\nnop +1",
+            "nop +1\njmp +2\njmp -2\nacc +7\njmp -4\nnop +0",
+        ] {
+            let computer = generator(source).unwrap();
+            let mut expected = Vec::new();
+            if !computer.execute().is_terminated() {
+                for index in 0..computer.instructions.len() {
+                    let mut patched = computer.clone();
+                    if patched.swap(index)
+                        && let Outcome::Terminated { accumulator } = patched.execute()
+                    {
+                        expected.push(Repair { index, accumulator });
+                    }
+                }
+            }
+
+            assert_eq!(computer.analyze_repairs().repairs, expected, "{source}");
+        }
+    }
+
+    #[test]
+    pub fn test_outcome() {
+        assert_eq!(
+            generator(SAMPLE).unwrap().execute(),
+            Outcome::InfiniteLoop {
+                accumulator: 5,
+                cycle: vec![1, 2, 6, 7, 3, 4]
+            }
+        );
+        assert_eq!(
+            generator(&SAMPLE.replace("jmp -4", "nop -4"))
+                .unwrap()
+                .execute(),
+            Outcome::Terminated { accumulator: 8 }
+        );
+        assert_eq!(
+            generator("acc +1\njmp +0").unwrap().execute(),
+            Outcome::InfiniteLoop {
+                accumulator: 1,
+                cycle: vec![1]
+            }
+        );
+        assert_eq!(
+            generator("acc +1\njmp -2").unwrap().execute(),
+            Outcome::OutOfBounds {
+                accumulator: 1,
+                pc: 1,
+                target: -1
+            }
+        );
+        assert_eq!(
+            generator("jmp +2\nacc +1").unwrap().execute(),
+            Outcome::Terminated { accumulator: 0 }
+        );
+        assert_eq!(
+            generator("nop +0\njmp +2").unwrap().execute(),
+            Outcome::OutOfBounds {
+                accumulator: 0,
+                pc: 1,
+                target: 3
+            }
+        );
+
+        // The accumulator is a VM word, so both agree past the range of the
+        // i32 arguments.
+        let source = "acc +2147483647\nacc -1\nacc +2";
+        let outcome = generator(source).unwrap().execute();
+        assert_eq!(
+            outcome,
+            Outcome::Terminated {
+                accumulator: 2_147_483_648
+            }
+        );
+        assert_eq!(outcome.accumulator(), 2_147_483_648);
+        assert_eq!(part1_vm(&generator_vm(source).unwrap()), 2_147_483_648);
+        assert!(outcome.is_terminated());
+        assert_eq!(
+            generator("").unwrap().execute(),
+            Outcome::Terminated { accumulator: 0 }
+        );
+    }
+
+    #[test]
+    pub fn test_trace() {
+        let trace = generator(SAMPLE).unwrap().trace();
+
+        assert_eq!(
+            trace.steps.iter().map(|step| step.pc).collect::<Vec<_>>(),
+            vec![0, 1, 2, 6, 7, 3, 4]
+        );
+        assert_eq!(
+            trace.steps[5],
+            TraceStep {
+                pc: 3,
+                instruction: Instruction::Acc(3),
+                before: 2,
+                after: 5
+            }
+        );
+        assert_eq!(trace.outcome, generator(SAMPLE).unwrap().execute());
+        assert_eq!(
+            trace.to_text(),
+            r"   0: nop +0     0 -> 0
+   1: acc +1     0 -> 1
+   2: jmp +4     1 -> 1
+   6: acc +1     1 -> 2
+   7: jmp -4     2 -> 2
+   3: acc +3     2 -> 5
+   4: jmp -3     5 -> 5
+infinite loop through 1 -> 2 -> 6 -> 7 -> 3 -> 4 with accumulator 5"
+        );
+
+        let trace = generator("acc +2\njmp +5").unwrap().trace();
+        assert_eq!(
+            trace.to_json(),
+            r#"{"steps":[{"pc":0,"instruction":"acc +2","before":0,"after":2}],"outcome":{"kind":"out_of_bounds","accumulator":2,"pc":1,"target":6}}"#
+        );
+    }
+
+    #[test]
+    pub fn test_coverage() {
+        let coverage = generator(SAMPLE).unwrap().coverage();
+
+        assert_eq!(coverage.counts, vec![1, 1, 1, 1, 1, 0, 1, 1, 0]);
+        assert_eq!(coverage.dead_code(), vec![5, 8]);
+        assert_eq!(coverage.loop_start(), Some(1));
+        assert_eq!(
+            coverage.to_text(),
+            r"   0: nop +0     1
+   1: acc +1     1  loop
+   2: jmp +4     1
+   3: acc +3     1
+   4: jmp -3     1
+   5: acc -99    0  dead
+   6: acc +1     1
+   7: jmp -4     1
+   8: acc +6     0  dead
+infinite loop through 1 -> 2 -> 6 -> 7 -> 3 -> 4 with accumulator 5"
+        );
+
+        let patched = generator(&SAMPLE.replace("jmp -4", "nop -4")).unwrap();
+        let coverage = patched.coverage();
+        assert_eq!(coverage.dead_code(), vec![3, 4, 5]);
+        assert_eq!(coverage.loop_start(), None);
+        assert_eq!(
+            coverage.to_json(),
+            concat!(
+                r#"{"instructions":["nop +0","acc +1","jmp +4","acc +3","jmp -3","acc -99","acc +1","nop -4","acc +6"],"#,
+                r#""counts":[1,1,1,0,0,0,1,1,1],"dead":[3,4,5],"loop_start":null,"#,
+                r#""outcome":{"kind":"terminated","accumulator":8}}"#
+            )
+        );
+
+        let original = generator(SAMPLE).unwrap().coverage();
+        assert_eq!(
+            original.compare_text(&coverage).unwrap(),
+            r"   0: nop +0     1  nop +0     1
+   1: acc +1     1  acc +1     1
+   2: jmp +4     1  jmp +4     1
+   3: acc +3     1  acc +3     0  lost
+   4: jmp -3     1  jmp -3     0  lost
+   5: acc -99    0  acc -99    0  dead
+   6: acc +1     1  acc +1     1
+   7: jmp -4     1  nop -4     1  patched
+   8: acc +6     0  acc +6     1  gained
+original: infinite loop through 1 -> 2 -> 6 -> 7 -> 3 -> 4 with accumulator 5
+patched: terminated with accumulator 8"
+        );
+        let json = original.compare_json(&coverage).unwrap();
+        assert!(json.starts_with(r#"{"changed":[7],"original":{"instructions":["nop +0","#));
+        assert!(json.ends_with(r#""outcome":{"kind":"terminated","accumulator":8}}}"#));
+
+        let coverage = generator("jmp +2\nacc +1\njmp -3").unwrap().coverage();
+        assert_eq!(original.compare_text(&coverage), None);
+        assert_eq!(original.compare_json(&coverage), None);
+        assert_eq!(coverage.counts, vec![1, 0, 1]);
+        assert!(coverage.to_json().ends_with(
+            r#""outcome":{"kind":"out_of_bounds","accumulator":0,"pc":2,"target":-1}}"#
+        ));
+    }
+
+    mod regression {
+        use super::*;
+
+        const INPUT: &str = include_str!("../../input/2020/day8.txt");
+        const ANSWERS: (i64, i64) = (1489, 1539);
+
+        #[test]
+        pub fn test() {
+            let input = INPUT.trim_end_matches('\n'); // Trims trailing newline
+            assert_eq!(part1(&generator(input).unwrap()), ANSWERS.0);
+            assert_eq!(part2(&generator(input).unwrap()), Some(ANSWERS.1));
+            assert_eq!(part2_cfg(&generator(input).unwrap()), Some(ANSWERS.1));
+            assert_eq!(part1_vm(&generator_vm(input).unwrap()), ANSWERS.0);
+        }
+    }
+}
@@ -0,0 +1,484 @@
+use std::{collections::HashMap, fmt};
+
+pub type Word = i64;
+
+// Opcodes and registers of `InstructionSet::handheld`.
+pub const ACC: usize = 0;
+pub const JMP: usize = 1;
+pub const NOP: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OperandKind {
+    Register,
+    Immediate,
+    // A relative jump distance, written either as a number or as a label.
+    Offset,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flow {
+    Next,
+    Jump(Word),
+    Halt,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VmError {
+    Overflow { pc: usize },
+    JumpOutOfBounds { pc: usize, target: Word },
+    UnknownOpcode { pc: usize, op: usize },
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VmError::Overflow { pc } => write!(f, "arithmetic overflow at {pc}"),
+            VmError::JumpOutOfBounds { pc, target } => {
+                write!(f, "jump from {pc} to {target} is out of bounds")
+            }
+            VmError::UnknownOpcode { pc, op } => write!(f, "unknown opcode {op} at {pc}"),
+        }
+    }
+}
+
+impl std::error::Error for VmError {}
+
+impl VmError {
+    // The same error, reported at `pc`.
+    fn at(self, pc: usize) -> Self {
+        match self {
+            VmError::Overflow { .. } => VmError::Overflow { pc },
+            VmError::JumpOutOfBounds { target, .. } => VmError::JumpOutOfBounds { pc, target },
+            VmError::UnknownOpcode { op, .. } => VmError::UnknownOpcode { pc, op },
+        }
+    }
+}
+
+// Register operands arrive as register indices, everything else as its value.
+// Errors may carry any pc; the machine reports them at the instruction that
+// raised them.
+pub type Exec = fn(&mut [Word], &[Word]) -> Result<Flow, VmError>;
+
+#[derive(Clone, Debug)]
+pub struct OpSpec {
+    pub name: String,
+    pub operands: Vec<OperandKind>,
+    pub exec: Exec,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct InstructionSet {
+    registers: Vec<String>,
+    ops: Vec<OpSpec>,
+}
+
+impl InstructionSet {
+    pub fn new(registers: &[&str]) -> Self {
+        Self {
+            registers: registers.iter().map(|r| (*r).to_owned()).collect(),
+            ops: Vec::new(),
+        }
+    }
+
+    // The console from the puzzle: one accumulator and `acc`, `jmp` and `nop`.
+    pub fn handheld() -> Self {
+        use OperandKind::*;
+
+        let mut set = Self::new(&["acc"]);
+        set.define("acc", &[Immediate], |regs, args| {
+            regs[ACC] = regs[ACC]
+                .checked_add(args[0])
+                .ok_or(VmError::Overflow { pc: 0 })?;
+            Ok(Flow::Next)
+        });
+        set.define("jmp", &[Offset], |_, args| Ok(Flow::Jump(args[0])));
+        set.define("nop", &[Offset], |_, _| Ok(Flow::Next));
+
+        set
+    }
+
+    // Adds an opcode, or replaces the one with the same name, returning its number.
+    pub fn define(&mut self, name: &str, operands: &[OperandKind], exec: Exec) -> usize {
+        let spec = OpSpec {
+            name: name.to_owned(),
+            operands: operands.to_vec(),
+            exec,
+        };
+
+        match self.opcode(name) {
+            Some(op) => {
+                self.ops[op] = spec;
+                op
+            }
+            None => {
+                self.ops.push(spec);
+                self.ops.len() - 1
+            }
+        }
+    }
+
+    pub fn add_register(&mut self, name: &str) -> usize {
+        self.register(name).unwrap_or_else(|| {
+            self.registers.push(name.to_owned());
+            self.registers.len() - 1
+        })
+    }
+
+    pub fn opcode(&self, name: &str) -> Option<usize> {
+        self.ops.iter().position(|spec| spec.name == name)
+    }
+
+    pub fn register(&self, name: &str) -> Option<usize> {
+        self.registers.iter().position(|r| r == name)
+    }
+
+    pub fn spec(&self, op: usize) -> Option<&OpSpec> {
+        self.ops.get(op)
+    }
+
+    pub fn registers(&self) -> &[String] {
+        &self.registers
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Instr {
+    pub op: usize,
+    pub args: Vec<Word>,
+}
+
+// `lines` holds the 1-based source line of each instruction.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Program {
+    pub instructions: Vec<Instr>,
+    pub lines: Vec<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AsmErrorKind {
+    UnknownOpcode(String),
+    OperandCount {
+        op: String,
+        expected: usize,
+        found: usize,
+    },
+    MalformedOperand(String),
+    UnknownRegister(String),
+    UnknownLabel(String),
+    DuplicateLabel(String),
+    MalformedLabel(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub kind: AsmErrorKind,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            AsmErrorKind::UnknownOpcode(op) => write!(f, "unknown opcode '{op}'"),
+            AsmErrorKind::OperandCount {
+                op,
+                expected,
+                found,
+            } => write!(f, "'{op}' takes {expected} operand(s), found {found}"),
+            AsmErrorKind::MalformedOperand(s) => write!(f, "malformed operand '{s}'"),
+            AsmErrorKind::UnknownRegister(s) => write!(f, "unknown register '{s}'"),
+            AsmErrorKind::UnknownLabel(s) => write!(f, "unknown label '{s}'"),
+            AsmErrorKind::DuplicateLabel(s) => write!(f, "duplicate label '{s}'"),
+            AsmErrorKind::MalformedLabel(s) => write!(f, "malformed label '{s}'"),
+        }
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+fn is_identifier(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// One instruction per line as `op operand, operand`; operands may also be
+// separated by spaces. `name:` defines a label, either on its own line or in
+// front of an instruction, and `#` or `;` start a comment.
+pub fn assemble(source: &str, set: &InstructionSet) -> Result<Program, AsmError> {
+    let mut labels = HashMap::new();
+    let mut pending = Vec::new();
+
+    for (line, text) in source.lines().enumerate().map(|(i, l)| (i + 1, l)) {
+        let error = |kind| AsmError { line, kind };
+        let mut code = text.split(['#', ';']).next().unwrap_or("").trim();
+
+        while let Some((label, rest)) = code.split_once(':') {
+            let label = label.trim();
+            if !is_identifier(label) {
+                return Err(error(AsmErrorKind::MalformedLabel(label.to_owned())));
+            }
+            if labels.insert(label, pending.len()).is_some() {
+                return Err(error(AsmErrorKind::DuplicateLabel(label.to_owned())));
+            }
+            code = rest.trim();
+        }
+
+        let mut words = code
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|w| !w.is_empty());
+        if let Some(name) = words.next() {
+            pending.push((line, name, words.collect::<Vec<_>>()));
+        }
+    }
+
+    let mut program = Program::default();
+    for (index, (line, name, operands)) in pending.into_iter().enumerate() {
+        let error = |kind| AsmError { line, kind };
+        let op = set
+            .opcode(name)
+            .ok_or_else(|| error(AsmErrorKind::UnknownOpcode(name.to_owned())))?;
+        let spec = &set.ops[op];
+
+        if spec.operands.len() != operands.len() {
+            return Err(error(AsmErrorKind::OperandCount {
+                op: name.to_owned(),
+                expected: spec.operands.len(),
+                found: operands.len(),
+            }));
+        }
+
+        let args = spec
+            .operands
+            .iter()
+            .zip(operands)
+            .map(|(kind, operand)| match kind {
+                OperandKind::Register => set
+                    .register(operand)
+                    .map(|r| r as Word)
+                    .ok_or_else(|| error(AsmErrorKind::UnknownRegister(operand.to_owned()))),
+                _ if operand.starts_with(|c: char| c.is_ascii_digit() || c == '+' || c == '-') => {
+                    operand
+                        .parse()
+                        .map_err(|_| error(AsmErrorKind::MalformedOperand(operand.to_owned())))
+                }
+                OperandKind::Offset if is_identifier(operand) => labels
+                    .get(operand)
+                    .map(|&target| target as Word - index as Word)
+                    .ok_or_else(|| error(AsmErrorKind::UnknownLabel(operand.to_owned()))),
+                _ => Err(error(AsmErrorKind::MalformedOperand(operand.to_owned()))),
+            })
+            .collect::<Result<_, _>>()?;
+
+        program.instructions.push(Instr { op, args });
+        program.lines.push(line);
+    }
+
+    Ok(program)
+}
+
+impl Program {
+    fn format(
+        &self,
+        set: &InstructionSet,
+        index: usize,
+        label: impl Fn(usize) -> Option<String>,
+    ) -> String {
+        let instr = &self.instructions[index];
+        let Some(spec) = set.spec(instr.op) else {
+            return format!("?{}", instr.op);
+        };
+
+        let operands = spec
+            .operands
+            .iter()
+            .zip(instr.args.iter())
+            .map(|(kind, &arg)| {
+                let target = usize::try_from(index as Word + arg).ok();
+                match kind {
+                    OperandKind::Register => usize::try_from(arg)
+                        .ok()
+                        .and_then(|r| set.registers.get(r).cloned())
+                        .unwrap_or_else(|| format!("r{arg}")),
+                    OperandKind::Offset => target
+                        .and_then(&label)
+                        .unwrap_or_else(|| format!("{arg:+}")),
+                    OperandKind::Immediate => format!("{arg:+}"),
+                }
+            });
+
+        std::iter::once(spec.name.clone())
+            .chain(operands)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    // Prints the program back in the puzzle's format.
+    pub fn disassemble(&self, set: &InstructionSet) -> String {
+        (0..self.instructions.len())
+            .map(|i| self.format(set, i, |_| None))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // Like `disassemble`, but jump targets become labels `L<index>`.
+    pub fn disassemble_labelled(&self, set: &InstructionSet) -> String {
+        let len = self.instructions.len();
+        let mut targets = vec![false; len + 1];
+        for (index, instr) in self.instructions.iter().enumerate() {
+            let Some(spec) = set.spec(instr.op) else {
+                continue;
+            };
+            for (kind, &arg) in spec.operands.iter().zip(instr.args.iter()) {
+                match usize::try_from(index as Word + arg) {
+                    Ok(target) if *kind == OperandKind::Offset && target <= len => {
+                        targets[target] = true
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let label = |target: usize| {
+            targets
+                .get(target)
+                .copied()
+                .unwrap_or(false)
+                .then(|| format!("L{target}"))
+        };
+        let mut lines = Vec::new();
+        for (index, &target) in targets.iter().enumerate() {
+            if target {
+                lines.push(format!("L{index}:"));
+            }
+            if index < len {
+                lines.push(format!("    {}", self.format(set, index, label)));
+            }
+        }
+
+        lines.join("\n")
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Exit {
+    // The pc stepped just past the last instruction.
+    Terminated,
+    // An instruction was about to run for the second time.
+    Looped,
+    Halted,
+}
+
+#[derive(Clone, Debug)]
+pub struct Machine<'a> {
+    set: &'a InstructionSet,
+    pub program: Program,
+    pub registers: Vec<Word>,
+    pc: usize,
+    // Where an instruction runs before its registers are committed.
+    scratch: Vec<Word>,
+}
+
+impl<'a> Machine<'a> {
+    pub fn new(set: &'a InstructionSet, program: Program) -> Self {
+        Self {
+            set,
+            program,
+            registers: vec![0; set.registers.len()],
+            pc: 0,
+            scratch: Vec::new(),
+        }
+    }
+
+    pub fn set(&self) -> &'a InstructionSet {
+        self.set
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    // Moves to any instruction, or just past the last one.
+    pub fn set_pc(&mut self, pc: usize) -> bool {
+        if pc > self.program.instructions.len() {
+            return false;
+        }
+
+        self.pc = pc;
+        true
+    }
+
+    pub fn is_terminated(&self) -> bool {
+        self.pc == self.program.instructions.len()
+    }
+
+    // Runs the instruction at `pc`. Stepping a terminated machine is a no-op, and
+    // a failed step leaves the registers and pc as they were.
+    pub fn step(&mut self) -> Result<Flow, VmError> {
+        let pc = self.pc;
+        let Some(instr) = self.program.instructions.get(pc) else {
+            return Ok(Flow::Halt);
+        };
+        let spec = self
+            .set
+            .spec(instr.op)
+            .ok_or(VmError::UnknownOpcode { pc, op: instr.op })?;
+
+        self.scratch.clone_from(&self.registers);
+        let flow = (spec.exec)(&mut self.scratch, &instr.args).map_err(|e| e.at(pc))?;
+
+        let next = match flow {
+            Flow::Next => pc + 1,
+            Flow::Jump(offset) => {
+                let target = (pc as Word).checked_add(offset);
+                target
+                    .and_then(|t| usize::try_from(t).ok())
+                    .filter(|&t| t <= self.program.instructions.len())
+                    .ok_or(VmError::JumpOutOfBounds {
+                        pc,
+                        target: target.unwrap_or(Word::MAX),
+                    })?
+            }
+            Flow::Halt => pc,
+        };
+
+        std::mem::swap(&mut self.registers, &mut self.scratch);
+        self.pc = next;
+
+        Ok(flow)
+    }
+
+    // Runs until termination or the first repeated instruction. A pc left past
+    // the end by shrinking the program halts the machine.
+    pub fn run(&mut self) -> Result<Exit, VmError> {
+        let mut seen = vec![false; self.program.instructions.len()];
+
+        loop {
+            if self.is_terminated() {
+                return Ok(Exit::Terminated);
+            }
+            if let Some(seen) = seen.get_mut(self.pc)
+                && std::mem::replace(seen, true)
+            {
+                return Ok(Exit::Looped);
+            }
+            if self.step()? == Flow::Halt {
+                return Ok(Exit::Halted);
+            }
+        }
+    }
+
+    // Runs at most `limit` instructions without loop detection, so programs
+    // may revisit instructions. `None` means the limit was reached first.
+    pub fn run_steps(&mut self, limit: usize) -> Result<Option<Exit>, VmError> {
+        for _ in 0..limit {
+            if self.is_terminated() {
+                return Ok(Some(Exit::Terminated));
+            }
+            if self.step()? == Flow::Halt {
+                return Ok(Some(Exit::Halted));
+            }
+        }
+
+        Ok(self.is_terminated().then_some(Exit::Terminated))
+    }
+}
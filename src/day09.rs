@@ -1,5 +1,9 @@
 use super::common::MinMaxIterator;
-use std::cmp::Ordering::{Equal, Greater, Less};
+use nohash_hasher::BuildNoHashHasher;
+use std::{
+    cmp::Ordering::{Equal, Greater, Less},
+    collections::{HashMap, VecDeque},
+};
 
 #[aoc_generator(day9)]
 pub fn generator(input: &str) -> Option<Vec<usize>> {
@@ -14,152 +18,298 @@ fn find_invalid((total, haystack): (&usize, &[usize])) -> Option<usize> {
         .xor(Some(*total))
 }
 
-fn find_range_bounds(inputs: &[usize], target: usize) -> (usize, usize) {
-    (0..inputs.len())
-        .find_map(|start| {
-            let mut sum = 0;
-            for (end, num) in inputs.iter().enumerate().skip(start) {
-                sum += num;
+// Pairs whose sum overflows are skipped, as no number can equal them.
+fn pair_sums(window: &VecDeque<usize>, value: usize) -> impl Iterator<Item = usize> + '_ {
+    window
+        .iter()
+        .filter(move |&&other| other != value)
+        .filter_map(move |&other| other.checked_add(value))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Invalid {
+    pub index: usize,
+    pub value: usize,
+}
 
-                return match sum.cmp(&target) {
-                    Less => continue,
-                    Equal => Some((start, end)),
-                    Greater => None,
-                };
+// Keeps the last `preamble` numbers and how often each sum of two of them with
+// different values occurs, so each new number costs O(preamble).
+#[derive(Debug, Clone)]
+pub struct XmasValidator {
+    preamble: usize,
+    window: VecDeque<usize>,
+    sums: HashMap<usize, usize, BuildNoHashHasher<usize>>,
+    index: usize,
+}
+
+impl XmasValidator {
+    pub fn new(preamble: usize) -> Self {
+        Self {
+            preamble,
+            window: VecDeque::with_capacity(preamble),
+            sums: HashMap::default(),
+            index: 0,
+        }
+    }
+
+    // Numbers are valid until the preamble has been read.
+    pub fn is_valid(&self, value: usize) -> bool {
+        self.window.len() < self.preamble || self.sums.contains_key(&value)
+    }
+
+    pub fn push(&mut self, value: usize) -> Option<Invalid> {
+        let invalid = (!self.is_valid(value)).then_some(Invalid {
+            index: self.index,
+            value,
+        });
+        self.index += 1;
+
+        if self.preamble == 0 {
+            return invalid;
+        }
+
+        let Self { window, sums, .. } = self;
+        if window.len() == self.preamble {
+            let oldest = window.pop_front().unwrap();
+            for sum in pair_sums(window, oldest) {
+                if let Some(count) = sums.get_mut(&sum) {
+                    *count -= 1;
+                    if *count == 0 {
+                        sums.remove(&sum);
+                    }
+                }
             }
+        }
+
+        for sum in pair_sums(window, value) {
+            *sums.entry(sum).or_default() += 1;
+        }
+        window.push_back(value);
+
+        invalid
+    }
+
+    // Yields every invalid number as it is read.
+    pub fn invalid<I: IntoIterator<Item = usize>>(
+        mut self,
+        numbers: I,
+    ) -> impl Iterator<Item = Invalid> {
+        numbers
+            .into_iter()
+            .filter_map(move |value| self.push(value))
+    }
+}
 
-            unreachable!()
-        })
-        .unwrap()
+// A contiguous range `start..=end` of at least two numbers summing to `target`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Weakness {
+    pub start: usize,
+    pub end: usize,
+    pub target: usize,
+    // The sum of the smallest and largest number in the range.
+    pub weakness: usize,
 }
 
-fn part1_with_size(inputs: &[usize], preamble: usize) -> usize {
+// Every range summing to one of `targets`, ordered by start, end and then
+// target, found in a single pass over the prefix sums.
+pub fn find_weaknesses(inputs: &[usize], targets: &[usize]) -> Vec<Weakness> {
+    let mut starts: HashMap<u128, Vec<usize>> = HashMap::new();
+    let mut found = Vec::new();
+    let mut prefix = 0_u128;
+    let mut previous = 0_u128;
+
+    for (end, &num) in inputs.iter().enumerate() {
+        // Ranges ending at `end` start at least one number earlier.
+        if end > 0 {
+            starts.entry(previous).or_default().push(end - 1);
+        }
+        previous = prefix;
+        prefix += num as u128;
+
+        for &target in targets {
+            let Some(before) = prefix.checked_sub(target as u128) else {
+                continue;
+            };
+
+            for &start in starts.get(&before).into_iter().flatten() {
+                let (min, max) = inputs[start..=end].iter().min_max().unwrap();
+                found.push(Weakness {
+                    start,
+                    end,
+                    target,
+                    weakness: min + max,
+                });
+            }
+        }
+    }
+
+    found.sort_unstable_by_key(|w| (w.start, w.end, w.target));
+    found.dedup();
+    found
+}
+
+pub fn find_weaknesses_for_invalid(inputs: &[usize], preamble: usize) -> Vec<Weakness> {
+    let targets = XmasValidator::new(preamble)
+        .invalid(inputs.iter().copied())
+        .map(|invalid| invalid.value)
+        .collect::<Vec<_>>();
+
+    find_weaknesses(inputs, &targets)
+}
+
+fn find_range_bounds(inputs: &[usize], target: usize) -> Option<(usize, usize)> {
+    (0..inputs.len()).find_map(|start| {
+        let mut sum = 0;
+        for (end, num) in inputs.iter().enumerate().skip(start) {
+            sum += num;
+
+            return match sum.cmp(&target) {
+                Less => continue,
+                // A range needs at least two numbers.
+                Equal if end == start => continue,
+                Equal => Some((start, end)),
+                Greater => None,
+            };
+        }
+
+        None
+    })
+}
+
+fn part1_with_size(inputs: &[usize], preamble: usize) -> Option<usize> {
+    XmasValidator::new(preamble)
+        .invalid(inputs.iter().copied())
+        .next()
+        .map(|invalid| invalid.value)
+}
+
+fn part1_windows_with_size(inputs: &[usize], preamble: usize) -> Option<usize> {
     inputs
         .windows(preamble + 1)
         .find_map(|range| find_invalid(range.split_last().unwrap()))
-        .unwrap()
 }
 
 #[aoc(day9, part1)]
-pub fn part1(inputs: &[usize]) -> usize {
+pub fn part1(inputs: &[usize]) -> Option<usize> {
     part1_with_size(inputs, 25)
 }
 
-fn part2_with_size(inputs: &[usize], preamble: usize) -> usize {
-    let (left, right) = find_range_bounds(inputs, part1_with_size(inputs, preamble));
-    let (min, max) = inputs[left..=right].iter().min_max().unwrap();
+#[aoc(day9, part1, windows)]
+pub fn part1_windows(inputs: &[usize]) -> Option<usize> {
+    part1_windows_with_size(inputs, 25)
+}
+
+fn weakness(inputs: &[usize], (left, right): (usize, usize)) -> Option<usize> {
+    let (min, max) = inputs[left..=right].iter().min_max()?;
+
+    Some(min + max)
+}
+
+fn part2_with_size(inputs: &[usize], preamble: usize) -> Option<usize> {
+    let bounds = find_range_bounds(inputs, part1_with_size(inputs, preamble)?)?;
 
-    min + max
+    weakness(inputs, bounds)
 }
 
 #[aoc(day9, part2)]
-pub fn part2(inputs: &[usize]) -> usize {
+pub fn part2(inputs: &[usize]) -> Option<usize> {
     part2_with_size(inputs, 25)
 }
 
 #[aoc(day9, part2, prefix1)]
-pub fn part2_prefix1(inputs: &[usize]) -> usize {
-    let (left, right) = find_range_bounds_prefix1(inputs, part1_with_size(inputs, 25));
-    let (min, max) = inputs[left..=right].iter().min_max().unwrap();
+pub fn part2_prefix1(inputs: &[usize]) -> Option<usize> {
+    let bounds = find_range_bounds_prefix1(inputs, part1_with_size(inputs, 25)?)?;
 
-    min + max
+    weakness(inputs, bounds)
 }
 
 #[aoc(day9, part2, prefix2)]
-pub fn part2_prefix2(inputs: &[usize]) -> usize {
-    let (left, right) = find_range_bounds_prefix2(inputs, part1_with_size(inputs, 25));
-    let (min, max) = inputs[left..=right].iter().min_max().unwrap();
+pub fn part2_prefix2(inputs: &[usize]) -> Option<usize> {
+    let bounds = find_range_bounds_prefix2(inputs, part1_with_size(inputs, 25)?)?;
 
-    min + max
+    weakness(inputs, bounds)
 }
 
 #[aoc(day9, part2, simple)]
-pub fn part2_simple(inputs: &[usize]) -> usize {
-    let target = part1_with_size(inputs, 25);
+pub fn part2_simple(inputs: &[usize]) -> Option<usize> {
+    let target = part1_with_size(inputs, 25)?;
     let mut sum = 0;
     let mut start = 0;
     let mut end = 0;
 
-    loop {
-        while sum < target {
-            sum += inputs[end];
+    while sum != target || end - start < 2 {
+        if sum <= target {
+            sum += inputs.get(end)?;
             end += 1;
-        }
-
-        while sum > target {
+        } else {
             sum -= inputs[start];
             start += 1;
         }
-
-        if sum == target {
-            break;
-        }
     }
 
-    let (min, max) = inputs[start..end].iter().min_max().unwrap();
+    weakness(inputs, (start, end - 1))
+}
+
+#[aoc(day9, part2, all)]
+pub fn part2_all(inputs: &[usize]) -> Option<usize> {
+    let target = part1_with_size(inputs, 25)?;
 
-    min + max
+    find_weaknesses(inputs, &[target])
+        .first()
+        .map(|w| w.weakness)
 }
 
-fn find_range_bounds_prefix1(inputs: &[usize], target: usize) -> (usize, usize) {
-    let prefixes = inputs
-        .iter()
-        .scan(0, |sum, &x| {
+fn find_range_bounds_prefix1(inputs: &[usize], target: usize) -> Option<(usize, usize)> {
+    // `prefixes[i]` is the sum of the first `i` numbers, so the range `start..end`
+    // sums to `prefixes[end] - prefixes[start]`.
+    let prefixes = std::iter::once(0)
+        .chain(inputs.iter().scan(0, |sum, &x| {
             *sum += x;
             Some(*sum)
-        })
+        }))
         .collect::<Vec<_>>();
 
-    prefixes
-        .iter()
-        .enumerate()
-        .find_map(|(start, &prefix)| {
-            for (end, sum) in prefixes
-                .iter()
-                .enumerate()
-                .skip(start + 2)
-                .map(|(end, &last)| (end, last - prefix))
-            {
-                return match sum.cmp(&target) {
-                    Less => continue,
-                    Equal => Some((start, end)),
-                    Greater => None,
-                };
-            }
+    prefixes.iter().enumerate().find_map(|(start, &prefix)| {
+        for (end, sum) in prefixes
+            .iter()
+            .enumerate()
+            .skip(start + 2)
+            .map(|(end, &last)| (end, last - prefix))
+        {
+            return match sum.cmp(&target) {
+                Less => continue,
+                Equal => Some((start, end - 1)),
+                Greater => None,
+            };
+        }
 
-            unreachable!()
-        })
-        .unwrap()
+        None
+    })
 }
 
-fn find_range_bounds_prefix2(inputs: &[usize], target: usize) -> (usize, usize) {
-    let prefixes = inputs
-        .iter()
-        .scan(0, |sum, &x| {
+fn find_range_bounds_prefix2(inputs: &[usize], target: usize) -> Option<(usize, usize)> {
+    let prefixes = std::iter::once(0)
+        .chain(inputs.iter().scan(0, |sum, &x| {
             *sum += x;
             Some(*sum)
-        })
+        }))
         .collect::<Vec<_>>();
 
-    prefixes
-        .iter()
-        .enumerate()
-        .find_map(|(start, &prefix)| {
-            prefixes
-                .iter()
-                .enumerate()
-                .skip(start + 2)
-                .map(|(end, &last)| (end, last - prefix))
-                .take_while(|&(_, sum)| sum <= target)
-                .find_map(|(end, sum)| {
-                    if sum == target {
-                        Some((start, end))
-                    } else {
-                        None
-                    }
-                })
-        })
-        .unwrap()
+    prefixes.iter().enumerate().find_map(|(start, &prefix)| {
+        prefixes
+            .iter()
+            .enumerate()
+            .skip(start + 2)
+            .map(|(end, &last)| (end, last - prefix))
+            .take_while(|&(_, sum)| sum <= target)
+            .find_map(|(end, sum)| {
+                if sum == target {
+                    Some((start, end - 1))
+                } else {
+                    None
+                }
+            })
+    })
 }
 
 #[cfg(test)]
@@ -200,12 +350,151 @@ mod tests {
 
     #[test]
     pub fn test1() {
-        assert_eq!(part1_with_size(&generator(SAMPLE).unwrap(), 5), 127);
+        assert_eq!(part1_with_size(&generator(SAMPLE).unwrap(), 5), Some(127));
+        assert_eq!(
+            part1_windows_with_size(&generator(SAMPLE).unwrap(), 5),
+            Some(127)
+        );
+    }
+
+    #[test]
+    pub fn test_validator() {
+        let inputs = generator(SAMPLE).unwrap();
+        let invalid = XmasValidator::new(5)
+            .invalid(inputs.iter().copied())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            invalid,
+            vec![Invalid {
+                index: 14,
+                value: 127
+            }]
+        );
+
+        // 1..=25 in some order, then the examples from the puzzle.
+        let preamble = (1..=25).rev().collect::<Vec<_>>();
+        for (next, valid) in [(26, true), (49, true), (100, false), (50, false)] {
+            let mut validator = XmasValidator::new(25);
+            preamble
+                .iter()
+                .for_each(|&n| assert_eq!(validator.push(n), None));
+            assert_eq!(validator.is_valid(next), valid, "{next}");
+        }
+
+        let mut validator = XmasValidator::new(25);
+        let numbers = (1..=19).chain(21..=25).chain([45, 26, 65, 64, 66]);
+        let invalid = numbers
+            .filter_map(|n| validator.push(n))
+            .map(|i| i.value)
+            .collect::<Vec<_>>();
+        assert_eq!(invalid, vec![65]);
+
+        // Equal values do not pair up, and evicted numbers stop counting.
+        let mut validator = XmasValidator::new(2);
+        assert_eq!(validator.push(3), None);
+        assert_eq!(validator.push(3), None);
+        assert_eq!(validator.push(6), Some(Invalid { index: 2, value: 6 }));
+        assert!(validator.is_valid(9));
+        assert_eq!(validator.push(1), Some(Invalid { index: 3, value: 1 }));
+        assert!(!validator.is_valid(9));
+        assert!(validator.is_valid(7));
+
+        let invalid = XmasValidator::new(2)
+            .invalid([usize::MAX, 1, usize::MAX, 2])
+            .collect::<Vec<_>>();
+        assert_eq!(
+            invalid,
+            vec![
+                Invalid {
+                    index: 2,
+                    value: usize::MAX
+                },
+                Invalid { index: 3, value: 2 }
+            ]
+        );
+        assert_eq!(XmasValidator::new(0).invalid([1, 2]).count(), 2);
     }
 
     #[test]
     pub fn test2() {
-        assert_eq!(part2_with_size(&generator(SAMPLE).unwrap(), 5), 62);
+        assert_eq!(part2_with_size(&generator(SAMPLE).unwrap(), 5), Some(62));
+    }
+
+    #[test]
+    pub fn test_weaknesses() {
+        let inputs = generator(SAMPLE).unwrap();
+
+        assert_eq!(
+            find_weaknesses(&inputs, &[127]),
+            vec![Weakness {
+                start: 2,
+                end: 5,
+                target: 127,
+                weakness: 62
+            }]
+        );
+        assert_eq!(
+            find_weaknesses_for_invalid(&inputs, 5),
+            find_weaknesses(&inputs, &[127])
+        );
+        assert!(find_weaknesses(&inputs, &[1]).is_empty());
+        assert_eq!(find_weaknesses(&inputs, &[127, 127]).len(), 1);
+        assert!(find_weaknesses(&[], &[0, 1]).is_empty());
+        assert!(find_weaknesses_for_invalid(&inputs, 19).is_empty());
+
+        let found = find_weaknesses(&inputs, &[55, 60, 35]);
+        assert_eq!(
+            found
+                .iter()
+                .map(|w| (w.start, w.end, w.target))
+                .collect::<Vec<_>>(),
+            vec![(0, 1, 55), (1, 2, 35), (1, 3, 60)]
+        );
+        assert_eq!(found[2].weakness, 15 + 25);
+
+        // Zeros allow several ranges to share a start or an end.
+        let found = find_weaknesses(&[0, 3, 0, 2, 5, 0], &[5]);
+        assert_eq!(
+            found.iter().map(|w| (w.start, w.end)).collect::<Vec<_>>(),
+            vec![(0, 3), (1, 3), (4, 5)]
+        );
+        assert_eq!(
+            find_weaknesses(&[usize::MAX, usize::MAX, 1], &[usize::MAX]),
+            vec![]
+        );
+
+        assert_eq!(part1(&inputs), None);
+        assert_eq!(part2(&inputs), None);
+        assert_eq!(part2_simple(&inputs), None);
+        assert_eq!(part2_with_size(&[1, 2, 3], 2), None);
+        assert_eq!(part2_with_size(&[1, 2, 4, 3], 2), None);
+    }
+
+    #[test]
+    pub fn test_variants() {
+        let inputs = generator(SAMPLE).unwrap();
+
+        for find in [
+            find_range_bounds,
+            find_range_bounds_prefix1,
+            find_range_bounds_prefix2,
+        ] {
+            assert_eq!(find(&inputs, 127), Some((2, 5)));
+            assert_eq!(find(&inputs, 55), Some((0, 1)));
+            assert_eq!(find(&inputs, 35), Some((1, 2)));
+            assert_eq!(find(&inputs, 20), None);
+        }
+
+        // 100 is first found at 9..=16, and 55 at the very start, 1..=10.
+        for (last, expected) in [(100, 25), (55, 11)] {
+            let inputs = (1..=25).chain([last]).collect::<Vec<_>>();
+
+            assert_eq!(part2(&inputs), Some(expected));
+            assert_eq!(part2_prefix1(&inputs), Some(expected));
+            assert_eq!(part2_prefix2(&inputs), Some(expected));
+            assert_eq!(part2_simple(&inputs), Some(expected));
+            assert_eq!(part2_all(&inputs), Some(expected));
+        }
     }
 
     mod regression {
@@ -217,8 +506,10 @@ mod tests {
         #[test]
         pub fn test() {
             let input = INPUT.trim_end_matches('\n'); // Trims trailing newline
-            assert_eq!(part1(&generator(input).unwrap()), ANSWERS.0);
-            assert_eq!(part2(&generator(input).unwrap()), ANSWERS.1);
+            assert_eq!(part1(&generator(input).unwrap()), Some(ANSWERS.0));
+            assert_eq!(part1_windows(&generator(input).unwrap()), Some(ANSWERS.0));
+            assert_eq!(part2(&generator(input).unwrap()), Some(ANSWERS.1));
+            assert_eq!(part2_all(&generator(input).unwrap()), Some(ANSWERS.1));
         }
     }
 }
@@ -1,12 +1,222 @@
+use num::{BigUint, CheckedAdd, One, Zero};
+
+// The largest step in joltage an adapter can take.
+pub const TOLERANCE: usize = 3;
+
+// The outlet, the sorted adapters and the device, which is rated `tolerance`
+// above the highest adapter.
+pub fn chain(adapters: &[usize], tolerance: usize) -> Vec<usize> {
+    let mut chain = Vec::with_capacity(adapters.len() + 2);
+    chain.push(0);
+    chain.extend_from_slice(adapters);
+    chain.sort_unstable();
+    chain.push(chain.last().unwrap() + tolerance);
+
+    chain
+}
+
 #[aoc_generator(day10)]
 pub fn generator(input: &str) -> Vec<usize> {
-    let mut adaptors: Vec<usize> = input.lines().map(|l| l.parse().unwrap()).collect();
+    let adaptors: Vec<usize> = input.lines().map(|l| l.parse().unwrap()).collect();
+
+    chain(&adaptors, TOLERANCE)
+}
+
+// How many steps of each size using every adapter takes, indexed by the size.
+// `None` if some step is 0 or larger than `tolerance`.
+pub fn gap_histogram(chain: &[usize], tolerance: usize) -> Option<Vec<usize>> {
+    let mut histogram = vec![0; tolerance + 1];
+    for w in chain.windows(2) {
+        match w[1] - w[0] {
+            0 => return None,
+            gap => *histogram.get_mut(gap)? += 1,
+        }
+    }
+
+    Some(histogram)
+}
+
+// The ways to get from the first to the last rating in `chain`, stepping up by
+// 1 to `tolerance` jolts at a time, or `None` if `T` overflows.
+pub fn count_arrangements<T>(chain: &[usize], tolerance: usize) -> Option<T>
+where
+    T: Clone + Zero + One + CheckedAdd,
+{
+    let mut ways: Vec<T> = Vec::with_capacity(chain.len());
+
+    for (j, &rating) in chain.iter().enumerate() {
+        let mut total = if j == 0 { T::one() } else { T::zero() };
+        for i in (0..j).rev().take_while(|&i| rating - chain[i] <= tolerance) {
+            if chain[i] < rating {
+                total = total.checked_add(&ways[i])?;
+            }
+        }
+
+        ways.push(total);
+    }
+
+    Some(ways.pop().unwrap_or_else(T::zero))
+}
+
+// The arrangements of a chain, ordered lexicographically by rating. Each one
+// is a list of ratings from the first to the last rating of the chain. Adapters
+// sharing a rating are interchangeable here, so every arrangement is listed once,
+// unlike in `count_arrangements`, which counts each choice of adapter.
+#[derive(Debug, Clone)]
+pub struct Arrangements {
+    chain: Vec<usize>,
+    tolerance: usize,
+    // The arrangements from each adapter onwards.
+    ways: Vec<BigUint>,
+}
+
+impl Arrangements {
+    pub fn new(chain: &[usize], tolerance: usize) -> Self {
+        let mut chain = chain.to_vec();
+        chain.dedup();
+        let len = chain.len();
+
+        let mut arrangements = Self {
+            ways: vec![BigUint::zero(); len],
+            chain,
+            tolerance,
+        };
+
+        for i in (0..len).rev() {
+            arrangements.ways[i] = if i + 1 == len {
+                BigUint::one()
+            } else {
+                arrangements
+                    .successors(i)
+                    .map(|j| &arrangements.ways[j])
+                    .sum()
+            };
+        }
+
+        arrangements
+    }
+
+    pub fn count(&self) -> BigUint {
+        self.ways.first().cloned().unwrap_or_default()
+    }
+
+    // The adapters that can follow `i` and still reach the end, in order.
+    fn successors(&self, i: usize) -> impl Iterator<Item = usize> + '_ {
+        let rating = self.chain[i];
+
+        (i + 1..self.chain.len())
+            .take_while(move |&j| self.chain[j] - rating <= self.tolerance)
+            .filter(move |&j| self.chain[j] > rating && !self.ways[j].is_zero())
+    }
 
-    adaptors.push(0);
-    adaptors.sort_unstable();
-    adaptors.push(adaptors.last().unwrap() + 3);
+    fn ratings(&self, path: &[usize]) -> Vec<usize> {
+        path.iter().map(|&i| self.chain[i]).collect()
+    }
+
+    pub fn iter(&self) -> ArrangementIter<'_> {
+        ArrangementIter {
+            arrangements: self,
+            path: Vec::new(),
+            started: false,
+        }
+    }
+
+    // The arrangement with `k` arrangements before it, found by skipping whole
+    // subtrees using their counts.
+    pub fn kth(&self, k: &BigUint) -> Option<Vec<usize>> {
+        if *k >= self.count() {
+            return None;
+        }
+
+        let mut k = k.clone();
+        let mut path = vec![0];
+        let mut i = 0;
+        while i + 1 < self.chain.len() {
+            for j in self.successors(i) {
+                if k < self.ways[j] {
+                    path.push(j);
+                    i = j;
+                    break;
+                }
+                k -= &self.ways[j];
+            }
+        }
+
+        Some(self.ratings(&path))
+    }
+
+    // A uniformly random arrangement. `random` must return uniformly random
+    // 64-bit words.
+    pub fn sample(&self, mut random: impl FnMut() -> u64) -> Option<Vec<usize>> {
+        let count = self.count();
+        if count.is_zero() {
+            return None;
+        }
+
+        // Draw just enough bits and retry if the number is too large, which
+        // happens less than half of the time.
+        let bits = count.bits();
+        let limit = BigUint::one() << bits;
+        loop {
+            let bytes = (0..bits.div_ceil(64))
+                .flat_map(|_| random().to_le_bytes())
+                .collect::<Vec<_>>();
+            let k = BigUint::from_bytes_le(&bytes) % &limit;
+
+            if k < count {
+                return self.kth(&k);
+            }
+        }
+    }
+}
 
-    adaptors
+// Walks the arrangements depth first. Dead ends are skipped, so each
+// arrangement costs at most its length times the tolerance.
+#[derive(Debug, Clone)]
+pub struct ArrangementIter<'a> {
+    arrangements: &'a Arrangements,
+    path: Vec<usize>,
+    started: bool,
+}
+
+impl ArrangementIter<'_> {
+    // Follows the first successor until the end of the chain.
+    fn descend(&mut self) {
+        while let Some(&i) = self.path.last() {
+            match self.arrangements.successors(i).next() {
+                Some(j) => self.path.push(j),
+                None => break,
+            }
+        }
+    }
+}
+
+impl Iterator for ArrangementIter<'_> {
+    type Item = Vec<usize>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.started {
+            self.started = true;
+            if self.arrangements.count().is_zero() {
+                return None;
+            }
+
+            self.path.push(0);
+            self.descend();
+            return Some(self.arrangements.ratings(&self.path));
+        }
+
+        loop {
+            let child = self.path.pop()?;
+            let &parent = self.path.last()?;
+
+            if let Some(next) = self.arrangements.successors(parent).find(|&j| j > child) {
+                self.path.push(next);
+                self.descend();
+                return Some(self.arrangements.ratings(&self.path));
+            }
+        }
+    }
 }
 
 #[aoc(day10, part1)]
@@ -40,24 +250,25 @@ pub fn part1_iterator(inputs: &[usize]) -> usize {
     ones * threes
 }
 
-#[aoc(day10, part2)]
-pub fn part2(inputs: &[usize]) -> usize {
-    let mut dp = vec![0; inputs.len()];
-    dp[0] = 1;
+#[aoc(day10, part1, histogram)]
+pub fn part1_histogram(inputs: &[usize]) -> Option<usize> {
+    let histogram = gap_histogram(inputs, TOLERANCE)?;
 
-    for (i, input) in inputs.iter().enumerate() {
-        for (j, output) in inputs.iter().enumerate().skip(i + 1) {
-            if output - input > 3 {
-                break;
-            }
+    Some(histogram[1] * histogram[3])
+}
 
-            dp[j] += dp[i];
-        }
-    }
+#[aoc(day10, part2)]
+pub fn part2(inputs: &[usize]) -> Option<usize> {
+    count_arrangements(inputs, TOLERANCE)
+}
 
-    dp.last().copied().unwrap()
+#[aoc(day10, part2, big)]
+pub fn part2_big(inputs: &[usize]) -> Option<BigUint> {
+    count_arrangements(inputs, TOLERANCE)
 }
 
+// Tracks the paths to the current rating and the two below it, so it assumes
+// distinct adapters and a tolerance of 3.
 #[aoc(day10, part2, alt)]
 pub fn part2_alt(inputs: &[usize]) -> usize {
     inputs
@@ -65,8 +276,9 @@ pub fn part2_alt(inputs: &[usize]) -> usize {
         .fold((1, 0, 0), |(paths_0, paths_1, paths_2), w| {
             match w[1] - w[0] {
                 3 => (paths_0, 0, 0),
-                1 => (paths_0 + paths_1, paths_0 + paths_2, paths_0),
-                _ => unreachable!(),
+                2 => (paths_0 + paths_1, 0, paths_0),
+                1 => (paths_0 + paths_1 + paths_2, paths_0, paths_1),
+                _ => (0, 0, 0),
             }
         })
         .0
@@ -81,7 +293,7 @@ pub fn part2_memoize(inputs: &[usize]) -> usize {
 
         let mut total = 0;
         for (j, input) in inputs.iter().enumerate().skip(index + 1) {
-            if input - inputs[index] > 3 {
+            if input - inputs[index] > TOLERANCE {
                 break;
             }
 
@@ -160,12 +372,198 @@ mod tests {
     pub fn test1() {
         assert_eq!(part1(&generator(SAMPLE1)), 35);
         assert_eq!(part1(&generator(SAMPLE2)), 220);
+        assert_eq!(part1_histogram(&generator(SAMPLE2)), Some(220));
     }
 
     #[test]
     pub fn test2() {
-        assert_eq!(part2(&generator(SAMPLE1)), 8);
-        assert_eq!(part2(&generator(SAMPLE2)), 19208);
+        assert_eq!(part2(&generator(SAMPLE1)), Some(8));
+        assert_eq!(part2(&generator(SAMPLE2)), Some(19208));
+        assert_eq!(part2_big(&generator(SAMPLE2)), Some(19208_u32.into()));
+        assert_eq!(part2_alt(&generator(SAMPLE1)), 8);
+        assert_eq!(part2_alt(&generator(SAMPLE2)), 19208);
+        assert_eq!(part2_memoize(&generator(SAMPLE2)), 19208);
+    }
+
+    fn brute_force(chain: &[usize], tolerance: usize) -> usize {
+        let inner = chain.len().saturating_sub(2);
+
+        (0..1_usize << inner)
+            .filter(|mask| {
+                let kept = std::iter::once(chain[0])
+                    .chain(
+                        (0..inner)
+                            .filter(|i| mask & (1 << i) != 0)
+                            .map(|i| chain[i + 1]),
+                    )
+                    .chain(std::iter::once(chain[chain.len() - 1]))
+                    .collect::<Vec<_>>();
+
+                kept.windows(2)
+                    .all(|w| (1..=tolerance).contains(&(w[1] - w[0])))
+            })
+            .count()
+    }
+
+    #[test]
+    pub fn test_tolerance() {
+        let adapters = generator(SAMPLE1);
+        let adapters = &adapters[1..adapters.len() - 1];
+
+        for tolerance in 1..=5 {
+            let chain = chain(adapters, tolerance);
+            assert_eq!(
+                count_arrangements::<usize>(&chain, tolerance),
+                Some(brute_force(&chain, tolerance)),
+                "{tolerance}"
+            );
+        }
+
+        assert_eq!(chain(&[5, 1, 2], 2), vec![0, 1, 2, 5, 7]);
+        assert_eq!(
+            count_arrangements::<usize>(&chain(&[5, 1, 2], 2), 2),
+            Some(0)
+        );
+        assert_eq!(count_arrangements::<usize>(&chain(&[], 3), 3), Some(1));
+        assert_eq!(count_arrangements::<usize>(&[], 3), Some(0));
+
+        // Duplicate ratings cannot follow each other.
+        let chain = chain(&[1, 1, 2], 3);
+        assert_eq!(
+            count_arrangements::<usize>(&chain, 3),
+            Some(brute_force(&chain, 3))
+        );
+        assert_eq!(gap_histogram(&chain, 3), None);
+
+        assert_eq!(part2_alt(&[0, 2, 4, 5, 8]), 2);
+        assert_eq!(
+            part2_alt(&[0, 2, 4, 5, 8]),
+            part2(&[0, 2, 4, 5, 8]).unwrap()
+        );
+        assert_eq!(part2_alt(&[0, 4, 7]), 0);
+    }
+
+    #[test]
+    pub fn test_histogram() {
+        assert_eq!(
+            gap_histogram(&generator(SAMPLE1), 3),
+            Some(vec![0, 7, 0, 5])
+        );
+        assert_eq!(gap_histogram(&generator(SAMPLE1), 2), None);
+        assert_eq!(gap_histogram(&chain(&[2, 4, 5], 2), 2), Some(vec![0, 1, 3]));
+        assert_eq!(part1_histogram(&[0, 4, 7]), None);
+    }
+
+    #[test]
+    pub fn test_big() {
+        // Every rating up to 200 with steps of up to 3 gives the tribonacci numbers.
+        let adapters = (1..200).collect::<Vec<_>>();
+        let chain = chain(&adapters, 1);
+        assert_eq!(count_arrangements::<usize>(&chain, 3), None);
+
+        let (mut a, mut b, mut c) = (BigUint::zero(), BigUint::zero(), BigUint::one());
+        for _ in 1..chain.len() {
+            (a, b, c) = (b.clone(), c.clone(), a + b + c);
+        }
+        assert_eq!(count_arrangements::<BigUint>(&chain, 3), Some(c));
+        assert_eq!(
+            count_arrangements::<u64>(&chain[..60], 3),
+            count_arrangements::<BigUint>(&chain[..60], 3).map(|n| n.try_into().unwrap())
+        );
+    }
+
+    // A fixed seed keeps the sampling tests repeatable.
+    fn xorshift(mut state: u64) -> impl FnMut() -> u64 {
+        move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        }
+    }
+
+    #[test]
+    pub fn test_arrangements() {
+        let arrangements = Arrangements::new(&generator(SAMPLE1), TOLERANCE);
+        let all = arrangements.iter().collect::<Vec<_>>();
+
+        assert_eq!(arrangements.count(), 8_u32.into());
+        assert_eq!(all.len(), 8);
+        assert_eq!(all[0], vec![0, 1, 4, 5, 6, 7, 10, 11, 12, 15, 16, 19, 22]);
+        assert_eq!(all[7], vec![0, 1, 4, 7, 10, 12, 15, 16, 19, 22]);
+        assert!(all.windows(2).all(|w| w[0] < w[1]));
+
+        for (k, arrangement) in all.iter().enumerate() {
+            assert_eq!(arrangements.kth(&k.into()).as_ref(), Some(arrangement));
+        }
+        assert_eq!(arrangements.kth(&8_u32.into()), None);
+
+        let arrangements = Arrangements::new(&generator(SAMPLE2), TOLERANCE);
+        let all = arrangements.iter().collect::<Vec<_>>();
+        assert_eq!(all.len(), 19208);
+        assert!(all.windows(2).all(|w| w[0] < w[1]));
+        assert!(all.iter().all(|a| gap_histogram(a, TOLERANCE).is_some()));
+        assert_eq!(
+            arrangements.kth(&12345_u32.into()).as_ref(),
+            Some(&all[12345])
+        );
+
+        // Repeated ratings give the same arrangements, which are listed once, and
+        // a broken chain has no arrangements.
+        let arrangements = Arrangements::new(&[0, 2, 2], 3);
+        assert_eq!(arrangements.iter().collect::<Vec<_>>(), vec![vec![0, 2]]);
+        assert_eq!(arrangements.count(), BigUint::one());
+        let arrangements = Arrangements::new(&[0, 1, 1, 2], 3);
+        let all = arrangements.iter().collect::<Vec<_>>();
+        assert_eq!(all, vec![vec![0, 1, 2], vec![0, 2]]);
+        assert_eq!(arrangements.count(), 2_u32.into());
+        assert_eq!(arrangements.kth(&BigUint::one()), Some(vec![0, 2]));
+        assert_eq!(count_arrangements::<usize>(&[0, 1, 1, 2], 3), Some(3));
+        let arrangements = Arrangements::new(&chain(&[5, 1, 2], 2), 2);
+        assert_eq!(arrangements.iter().next(), None);
+        assert_eq!(arrangements.kth(&BigUint::zero()), None);
+        assert_eq!(arrangements.sample(xorshift(1)), None);
+        assert_eq!(Arrangements::new(&[], 3).iter().next(), None);
+        assert_eq!(
+            Arrangements::new(&[4], 3).iter().collect::<Vec<_>>(),
+            vec![vec![4]]
+        );
+    }
+
+    #[test]
+    pub fn test_sample() {
+        let arrangements = Arrangements::new(&generator(SAMPLE1), TOLERANCE);
+        let all = arrangements.iter().collect::<Vec<_>>();
+
+        let mut counts = vec![0; all.len()];
+        let mut random = xorshift(0x2020_1210);
+        for _ in 0..8000 {
+            let sample = arrangements.sample(&mut random).unwrap();
+            counts[all.iter().position(|a| *a == sample).unwrap()] += 1;
+        }
+        assert!(
+            counts.iter().all(|&n| (850..1150).contains(&n)),
+            "{counts:?}"
+        );
+
+        // Huge counts still yield real chains.
+        let arrangements = Arrangements::new(&chain(&(1..200).collect::<Vec<_>>(), 1), 3);
+        assert_eq!(
+            Some(arrangements.count()),
+            count_arrangements(&chain(&(1..200).collect::<Vec<_>>(), 1), 3)
+        );
+        assert_eq!(
+            arrangements.kth(&BigUint::zero()),
+            Some((0..=200).collect())
+        );
+        assert_eq!(
+            arrangements.kth(&(arrangements.count() - 1_u32)),
+            Some((0..=198).step_by(3).chain([200]).collect())
+        );
+        let sample = arrangements.sample(xorshift(7)).unwrap();
+        assert_eq!((sample[0], sample[sample.len() - 1]), (0, 200));
+        assert!(gap_histogram(&sample, 3).is_some());
+        assert_eq!(arrangements.iter().nth(3), arrangements.kth(&3_u32.into()));
     }
 
     mod regression {
@@ -178,7 +576,9 @@ mod tests {
         pub fn test() {
             let input = INPUT.trim_end_matches('\n'); // Trims trailing newline
             assert_eq!(part1(&generator(input)), ANSWERS.0);
-            assert_eq!(part2(&generator(input)), ANSWERS.1);
+            assert_eq!(part2(&generator(input)), Some(ANSWERS.1));
+            assert_eq!(part2_big(&generator(input)), Some(ANSWERS.1.into()));
+            assert_eq!(part2_alt(&generator(input)), ANSWERS.1);
         }
     }
 }
@@ -1,4 +1,4 @@
-#[derive(PartialEq, Copy, Clone)]
+#[derive(PartialEq, Eq, Copy, Clone)]
 enum SeatState {
     Empty,
     Occupied,
@@ -151,6 +151,217 @@ impl Floor {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Neighborhood {
+    // The eight surrounding cells.
+    Adjacent,
+    // The first seat in each of the eight directions, looking at most
+    // `max_distance` cells away, or to the edge if `None`.
+    LineOfSight { max_distance: Option<usize> },
+    // The cells a chess knight could move to.
+    Knight,
+    // The cells at these (row, column) offsets.
+    Custom(Vec<(isize, isize)>),
+}
+
+const DIRECTIONS: [(isize, isize); 8] = [
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+    (0, -1),
+    (0, 1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+];
+
+const KNIGHT_MOVES: [(isize, isize); 8] = [
+    (-2, -1),
+    (-2, 1),
+    (-1, -2),
+    (-1, 2),
+    (1, -2),
+    (1, 2),
+    (2, -1),
+    (2, 1),
+];
+
+// An empty seat is taken when at most `occupy_at_most` of the seats it watches
+// are occupied, and an occupied seat is left when at least `vacate_at_least`
+// are. `floor_blocks_sight` stops a line of sight at the first floor cell.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rules {
+    pub neighborhood: Neighborhood,
+    pub occupy_at_most: usize,
+    pub vacate_at_least: usize,
+    pub floor_blocks_sight: bool,
+}
+
+impl Rules {
+    pub fn adjacent() -> Self {
+        Self {
+            neighborhood: Neighborhood::Adjacent,
+            occupy_at_most: 0,
+            vacate_at_least: 4,
+            floor_blocks_sight: false,
+        }
+    }
+
+    pub fn line_of_sight() -> Self {
+        Self {
+            neighborhood: Neighborhood::LineOfSight { max_distance: None },
+            occupy_at_most: 0,
+            vacate_at_least: 5,
+            floor_blocks_sight: false,
+        }
+    }
+}
+
+impl Floor {
+    fn width(&self) -> usize {
+        self.floor.first().map_or(0, Vec::len)
+    }
+
+    fn cell(&self, row: usize, col: usize, (dr, dc): (isize, isize)) -> Option<(usize, usize)> {
+        let row = row.checked_add_signed(dr)?;
+        let col = col.checked_add_signed(dc)?;
+
+        (row < self.floor.len() && col < self.floor[row].len()).then_some((row, col))
+    }
+
+    // For every cell, the seats it watches as indices into the flattened floor.
+    // Floor never turns into seats, so this holds for the whole simulation.
+    fn watched(&self, rules: &Rules) -> Vec<Vec<usize>> {
+        let width = self.width();
+        let is_seat = |(r, c): (usize, usize)| self.floor[r][c] != SeatState::Blank;
+        let offsets = match &rules.neighborhood {
+            Neighborhood::Adjacent => DIRECTIONS.to_vec(),
+            Neighborhood::Knight => KNIGHT_MOVES.to_vec(),
+            Neighborhood::Custom(offsets) => {
+                let mut offsets = offsets.clone();
+                offsets.sort_unstable();
+                offsets.dedup();
+                offsets.retain(|&offset| offset != (0, 0));
+                offsets
+            }
+            Neighborhood::LineOfSight { .. } => Vec::new(),
+        };
+
+        let mut watched = Vec::with_capacity(self.floor.len() * width);
+        for (r, row) in self.floor.iter().enumerate() {
+            for (c, &seat) in row.iter().enumerate() {
+                let mut seats = Vec::new();
+                if seat == SeatState::Blank {
+                    watched.push(seats);
+                    continue;
+                }
+
+                if let Neighborhood::LineOfSight { max_distance } = rules.neighborhood {
+                    for direction in DIRECTIONS {
+                        let mut cell = (r, c);
+                        for _ in 0..max_distance.unwrap_or(usize::MAX) {
+                            let Some(next) = self.cell(cell.0, cell.1, direction) else {
+                                break;
+                            };
+                            cell = next;
+
+                            if is_seat(cell) {
+                                seats.push(cell.0 * width + cell.1);
+                                break;
+                            }
+                            if rules.floor_blocks_sight {
+                                break;
+                            }
+                        }
+                    }
+                } else {
+                    seats.extend(
+                        offsets
+                            .iter()
+                            .filter_map(|&offset| self.cell(r, c, offset))
+                            .filter(|&cell| is_seat(cell))
+                            .map(|(r, c)| r * width + c),
+                    );
+                }
+
+                watched.push(seats);
+            }
+        }
+
+        watched
+    }
+
+    fn step_with(&self, rules: &Rules, watched: &[Vec<usize>]) -> Floor {
+        let width = self.width();
+        let at = |i: usize| self.floor[i / width][i % width];
+
+        let floor = self
+            .floor
+            .iter()
+            .enumerate()
+            .map(|(r, row)| {
+                row.iter()
+                    .enumerate()
+                    .map(|(c, &seat)| {
+                        let count = watched[r * width + c]
+                            .iter()
+                            .map(|&i| at(i).occupied())
+                            .sum::<usize>();
+
+                        match seat {
+                            SeatState::Empty if count <= rules.occupy_at_most => {
+                                SeatState::Occupied
+                            }
+                            SeatState::Occupied if count >= rules.vacate_at_least => {
+                                SeatState::Empty
+                            }
+                            seat => seat,
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Floor { floor }
+    }
+
+    // One round of seating under `rules`.
+    pub fn step(&self, rules: &Rules) -> Floor {
+        self.step_with(rules, &self.watched(rules))
+    }
+
+    // The floor once nobody moves any more, or `None` if the seating keeps
+    // cycling. Uses Brent's algorithm, so only two floors are kept.
+    pub fn settle(&self, rules: &Rules) -> Option<Floor> {
+        let watched = self.watched(rules);
+        let mut power = 1;
+        let mut period = 1;
+        let mut tortoise = self.clone();
+        let mut hare = self.step_with(rules, &watched);
+
+        while tortoise != hare {
+            if power == period {
+                tortoise = hare.clone();
+                power *= 2;
+                period = 0;
+            }
+
+            hare = hare.step_with(rules, &watched);
+            period += 1;
+        }
+
+        (period == 1).then_some(hare)
+    }
+
+    pub fn occupied(&self) -> usize {
+        self.floor
+            .iter()
+            .flat_map(|row| row.iter())
+            .map(|s| s.occupied())
+            .sum()
+    }
+}
+
 struct QueenIterator<'a> {
     row: usize,
     col: usize,
@@ -170,23 +381,28 @@ impl<'a> Iterator for QueenIterator<'a> {
     }
 }
 
+// Returns `None` for an unknown cell or rows of different lengths.
 #[aoc_generator(day11)]
-pub fn generator(input: &str) -> Floor {
-    Floor {
-        floor: input
-            .lines()
-            .map(|line| {
-                line.chars()
-                    .map(|x| match x {
-                        'L' => SeatState::Empty,
-                        '.' => SeatState::Blank,
-                        '#' => SeatState::Occupied,
-                        _ => panic!("unknown"),
-                    })
-                    .collect()
-            })
-            .collect(),
-    }
+pub fn generator(input: &str) -> Option<Floor> {
+    let floor = input
+        .lines()
+        .map(|line| {
+            line.chars()
+                .map(|x| match x {
+                    'L' => Some(SeatState::Empty),
+                    '.' => Some(SeatState::Blank),
+                    '#' => Some(SeatState::Occupied),
+                    _ => None,
+                })
+                .collect()
+        })
+        .collect::<Option<Vec<Vec<_>>>>()?;
+
+    let width = floor.first().map_or(0, Vec::len);
+    floor
+        .iter()
+        .all(|row| row.len() == width)
+        .then_some(Floor { floor })
 }
 
 fn solve<F>(mut current: Floor, threshold: usize, count_fn: F) -> usize
@@ -213,12 +429,22 @@ where
 }
 
 #[aoc(day11, part1)]
-pub fn part1(inputs: &Floor) -> usize {
-    solve(inputs.clone(), 4, Floor::count_neighbors)
+pub fn part1(inputs: &Floor) -> Option<usize> {
+    Some(inputs.settle(&Rules::adjacent())?.occupied())
 }
 
 #[aoc(day11, part2)]
-pub fn part2(inputs: &Floor) -> usize {
+pub fn part2(inputs: &Floor) -> Option<usize> {
+    Some(inputs.settle(&Rules::line_of_sight())?.occupied())
+}
+
+#[aoc(day11, part1, closure)]
+pub fn part1_closure(inputs: &Floor) -> usize {
+    solve(inputs.clone(), 4, Floor::count_neighbors)
+}
+
+#[aoc(day11, part2, closure)]
+pub fn part2_closure(inputs: &Floor) -> usize {
     solve(inputs.clone(), 5, Floor::count_queen)
 }
 
@@ -240,19 +466,118 @@ L.LLLLL.LL";
     #[test]
     pub fn test_input() {
         assert_eq!(
-            format!("{:?}", generator(SAMPLE)).trim_end_matches('\n'),
+            format!("{:?}", generator(SAMPLE).unwrap()).trim_end_matches('\n'),
             SAMPLE
         );
     }
 
     #[test]
     pub fn test1() {
-        assert_eq!(part1(&generator(SAMPLE)), 37);
+        assert_eq!(part1(&generator(SAMPLE).unwrap()), Some(37));
+        assert_eq!(part1_closure(&generator(SAMPLE).unwrap()), 37);
     }
 
     #[test]
     pub fn test2() {
-        assert_eq!(part2(&generator(SAMPLE)), 26);
+        assert_eq!(part2(&generator(SAMPLE).unwrap()), Some(26));
+        assert_eq!(part2_closure(&generator(SAMPLE).unwrap()), 26);
+    }
+
+    #[test]
+    pub fn test_step() {
+        let floor = generator(SAMPLE).unwrap();
+        let once = floor.step(&Rules::adjacent());
+        assert_eq!(once.occupied(), 71);
+        assert_eq!(
+            format!("{:?}", once.step(&Rules::adjacent()))
+                .lines()
+                .next(),
+            Some("#.LL.L#.##")
+        );
+
+        let settled = floor.settle(&Rules::line_of_sight()).unwrap();
+        assert_eq!(settled.step(&Rules::line_of_sight()), settled);
+        assert_eq!(format!("{:?}", settled).lines().last(), Some("#.L#LL#.L#"));
+    }
+
+    #[test]
+    pub fn test_rules() {
+        let floor = generator(SAMPLE).unwrap();
+        let occupied = |rules: &Rules| floor.settle(rules).map(|f| f.occupied());
+
+        // A line of sight that stops after one cell, or at floor, is adjacency.
+        for rules in [
+            Rules {
+                neighborhood: Neighborhood::LineOfSight {
+                    max_distance: Some(1),
+                },
+                ..Rules::adjacent()
+            },
+            Rules {
+                neighborhood: Neighborhood::LineOfSight { max_distance: None },
+                floor_blocks_sight: true,
+                ..Rules::adjacent()
+            },
+            Rules {
+                neighborhood: Neighborhood::Custom(
+                    DIRECTIONS.iter().copied().chain([(0, 0), (1, 1)]).collect(),
+                ),
+                ..Rules::adjacent()
+            },
+        ] {
+            assert_eq!(occupied(&rules), Some(37), "{rules:?}");
+        }
+
+        let long_sight = Rules {
+            neighborhood: Neighborhood::LineOfSight {
+                max_distance: Some(100),
+            },
+            ..Rules::line_of_sight()
+        };
+        assert_eq!(occupied(&long_sight), Some(26));
+
+        let knight = Rules {
+            neighborhood: Neighborhood::Knight,
+            ..Rules::adjacent()
+        };
+        let custom_knight = Rules {
+            neighborhood: Neighborhood::Custom(KNIGHT_MOVES.to_vec()),
+            ..Rules::adjacent()
+        };
+        assert_eq!(occupied(&knight), occupied(&custom_knight));
+
+        // Only the seat to the right is watched.
+        let right = Rules {
+            neighborhood: Neighborhood::Custom(vec![(0, 1)]),
+            occupy_at_most: 0,
+            vacate_at_least: 1,
+            floor_blocks_sight: false,
+        };
+        assert_eq!(
+            format!("{:?}", generator("LLL").unwrap().settle(&right).unwrap()),
+            "#L#\n"
+        );
+
+        // Seats that fill whenever they can and empty at the first neighbor
+        // never settle.
+        let restless = Rules {
+            occupy_at_most: 8,
+            vacate_at_least: 1,
+            ..Rules::adjacent()
+        };
+        assert_eq!(generator("LL").unwrap().settle(&restless), None);
+        assert_eq!(
+            generator("L.L")
+                .unwrap()
+                .settle(&restless)
+                .unwrap()
+                .occupied(),
+            2
+        );
+        assert_eq!(
+            generator("").unwrap().settle(&restless).unwrap().occupied(),
+            0
+        );
     }
 
     mod regression {
@@ -264,8 +589,8 @@ L.LLLLL.LL";
         #[test]
         pub fn test() {
             let input = INPUT.trim_end_matches('\n'); // Trims trailing newline
-            assert_eq!(part1(&generator(input)), ANSWERS.0);
-            assert_eq!(part2(&generator(input)), ANSWERS.1);
+            assert_eq!(part1(&generator(input).unwrap()), Some(ANSWERS.0));
+            assert_eq!(part2(&generator(input).unwrap()), Some(ANSWERS.1));
         }
     }
 }